@@ -0,0 +1,74 @@
+// Compares the window matchers against the naive loop search_seq used to run.
+// Windows are synthetic AT rich (~65%) sequences, roughly what a plant promoter
+// looks like, at the window sizes kbrecondo is usually run with.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use kbrecondo::matcher;
+use std::hint::black_box;
+
+fn promoter_window(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    let mut window: Vec<u8> = Vec::with_capacity(len);
+    for _ in 0..len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let nuc = match state % 100 {
+            0..=32 => b'a',
+            33..=65 => b't',
+            66..=82 => b'c',
+            _ => b'g',
+        };
+        window.push(nuc);
+    }
+    window
+}
+
+// the loop search_seq used before the matchers were added
+fn naive(search_area: &[u8], bpat: &[u8]) -> Vec<usize> {
+    let mut occurances: Vec<usize> = Vec::new();
+    for i in 0..search_area.len() - bpat.len() {
+        if bpat == &search_area[i..i + bpat.len()] {
+            occurances.push(i);
+        }
+    }
+    occurances
+}
+
+fn exact(c: &mut Criterion) {
+    let patterns: [&[u8]; 3] = [b"acgtg", b"tataaatacacg", b"ccacgtggcaagtgtaatcacatatgcacg"];
+    for size in [1000, 5000] {
+        let window = promoter_window(2 * size + 3000, 42);
+        let mut group = c.benchmark_group(format!("exact_window_{}", size));
+        for pattern in patterns {
+            let len = pattern.len();
+            group.bench_with_input(BenchmarkId::new("naive", len), pattern, |b, p| {
+                b.iter(|| naive(black_box(&window), black_box(p)))
+            });
+            group.bench_with_input(BenchmarkId::new("memmem", len), pattern, |b, p| {
+                b.iter(|| matcher::memmem_find(black_box(&window), black_box(p)))
+            });
+            group.bench_with_input(BenchmarkId::new("shift_or", len), pattern, |b, p| {
+                b.iter(|| matcher::shift_or_mismatch(black_box(&window), black_box(p), 0))
+            });
+        }
+        group.finish();
+    }
+}
+
+fn mismatch(c: &mut Criterion) {
+    let window = promoter_window(5000, 7);
+    let pattern: &[u8] = b"tataaatacacg";
+    let mut group = c.benchmark_group("mismatch_window_1000");
+    for k in [1, 2, 3] {
+        group.bench_with_input(BenchmarkId::new("shift_or", k), &k, |b, &k| {
+            b.iter(|| matcher::shift_or_mismatch(black_box(&window), black_box(pattern), k))
+        });
+        group.bench_with_input(BenchmarkId::new("hamming", k), &k, |b, &k| {
+            b.iter(|| matcher::hamming_find(black_box(&window), black_box(pattern), k))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, exact, mismatch);
+criterion_main!(benches);
@@ -0,0 +1,232 @@
+//! Settings read from TOML files, so data directories, species and default
+//! search parameters do not have to be given on every run.
+//!
+//! The user file (`$XDG_CONFIG_HOME/kbrecondo/config.toml`, falling back to
+//! `~/.config/kbrecondo/config.toml`) is read first, then the project file
+//! `kbrecondo.toml` in the current directory, each overriding what was set
+//! before. Command line flags override both. Relative paths are taken
+//! relative to the file they are written in.
+//!
+//! ```toml
+//! [data]
+//! genomes = "/data/legumeinfo/genomes"
+//! annotations = "/data/legumeinfo/annotations"
+//!
+//! [defaults]
+//! species = "medtr"
+//! seq_type = "cds"
+//! window = 2000
+//! mismatches = 0
+//! coordinates = "1"
+//!
+//! [species.medtr]
+//! name = "Medicago truncatula"
+//! genome_template = "{genotype}.{assembly}.*/{species}.{dir}.genome_main.fna.gz"
+//! annotation_template = "{genotype}.{assembly}.{annotation}.*/{species}.{dir}.{seq_type}.f*a.gz"
+//! header_dialect = "legumeinfo"
+//! ```
+//!
+//! See [`crate::paths`] for the placeholders of the templates.
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_FILE: &str = "kbrecondo.toml";
+
+/// legumeinfo genome FASTA, e.g.
+/// `A17.gnm5.FL4S/medtr.A17.gnm5.FL4S.genome_main.fna.gz`
+pub const GENOME_TEMPLATE: &str = "{genotype}.{assembly}.*/{species}.{dir}.genome_main.fna.gz";
+/// legumeinfo annotation FASTA, e.g.
+/// `A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.cds.fna.gz`, or `.faa.gz`
+/// for the proteins
+pub const ANNOTATION_TEMPLATE: &str =
+    "{genotype}.{assembly}.{annotation}.*/{species}.{dir}.{seq_type}.f*a.gz";
+
+/// Where the genome and annotation directories are.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataDirs {
+    pub genomes: Option<PathBuf>,
+    pub annotations: Option<PathBuf>,
+}
+
+/// Search parameters used when no flag is given.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    pub species: Option<String>,
+    pub seq_type: Option<String>,
+    pub window: Option<usize>,
+    pub mismatches: Option<usize>,
+    /// `0` or `1`, see [`crate::window::Coordinates`].
+    pub coordinates: Option<String>,
+}
+
+/// One `[species.<tag>]` table, the tag is the species part of the file names.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpeciesEntry {
+    pub name: Option<String>,
+    /// Overrides `[data] genomes` for this species.
+    pub genomes: Option<PathBuf>,
+    /// Overrides `[data] annotations` for this species.
+    pub annotations: Option<PathBuf>,
+    /// Path of the genome inside the genome directory, see
+    /// [`GENOME_TEMPLATE`].
+    pub genome_template: Option<String>,
+    /// Path of an annotation inside the annotation directory, see
+    /// [`ANNOTATION_TEMPLATE`].
+    pub annotation_template: Option<String>,
+    /// How the annotation headers are written, see [`crate::dialect`].
+    pub header_dialect: Option<String>,
+    /// Regex reading the annotation headers, overrides `header_dialect`.
+    pub header_regex: Option<String>,
+}
+
+/// The merged contents of every config file read.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub data: DataDirs,
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub species: BTreeMap<String, SpeciesEntry>,
+}
+
+impl Config {
+    /// Reads the user and project files, or only `explicit` when given.
+    /// Missing user and project files are skipped.
+    pub fn load(explicit: Option<&Path>) -> Result<Config> {
+        let mut config = Config::default();
+        match explicit {
+            Some(path) => config.merge(Config::read(path)?),
+            None => {
+                let project = env::current_dir()
+                    .map_err(|err| Error::io(".".as_ref(), err))?
+                    .join(PROJECT_FILE);
+                for path in user_file().into_iter().chain([project]) {
+                    if path.is_file() {
+                        config.merge(Config::read(&path)?);
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Reads one config file.
+    pub fn read(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        let mut config: Config = toml::from_str(&text).map_err(|err| {
+            let parse = Error::parse("config", err.message()).in_file(path);
+            match err.span() {
+                Some(span) => parse.at_line(text[..span.start].matches('\n').count() + 1),
+                None => parse,
+            }
+        })?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        config.make_relative_to(base);
+        Ok(config)
+    }
+
+    fn make_relative_to(&mut self, base: &Path) {
+        let fix = |dir: &mut Option<PathBuf>| {
+            if let Some(dir) = dir {
+                *dir = base.join(&*dir);
+            }
+        };
+        fix(&mut self.data.genomes);
+        fix(&mut self.data.annotations);
+        for entry in self.species.values_mut() {
+            fix(&mut entry.genomes);
+            fix(&mut entry.annotations);
+        }
+    }
+
+    /// Overrides every setting `other` has.
+    pub fn merge(&mut self, other: Config) {
+        fn set<T>(to: &mut Option<T>, from: Option<T>) {
+            if from.is_some() {
+                *to = from;
+            }
+        }
+        set(&mut self.data.genomes, other.data.genomes);
+        set(&mut self.data.annotations, other.data.annotations);
+        set(&mut self.defaults.species, other.defaults.species);
+        set(&mut self.defaults.seq_type, other.defaults.seq_type);
+        set(&mut self.defaults.window, other.defaults.window);
+        set(&mut self.defaults.mismatches, other.defaults.mismatches);
+        set(&mut self.defaults.coordinates, other.defaults.coordinates);
+        for (tag, from) in other.species {
+            let to = self.species.entry(tag).or_default();
+            set(&mut to.name, from.name);
+            set(&mut to.genomes, from.genomes);
+            set(&mut to.annotations, from.annotations);
+            set(&mut to.genome_template, from.genome_template);
+            set(&mut to.annotation_template, from.annotation_template);
+            set(&mut to.header_dialect, from.header_dialect);
+            set(&mut to.header_regex, from.header_regex);
+        }
+    }
+
+    /// Genome directory of `species`, `./genomes` if none is configured.
+    pub fn genomes(&self, species: &str) -> PathBuf {
+        self.species
+            .get(species)
+            .and_then(|entry| entry.genomes.clone())
+            .or_else(|| self.data.genomes.clone())
+            .unwrap_or_else(|| PathBuf::from("genomes"))
+    }
+
+    /// Annotation directory of `species`, `./annotations` if none is
+    /// configured.
+    pub fn annotations(&self, species: &str) -> PathBuf {
+        self.species
+            .get(species)
+            .and_then(|entry| entry.annotations.clone())
+            .or_else(|| self.data.annotations.clone())
+            .unwrap_or_else(|| PathBuf::from("annotations"))
+    }
+
+    pub fn genome_template(&self, species: &str) -> String {
+        self.species
+            .get(species)
+            .and_then(|entry| entry.genome_template.clone())
+            .unwrap_or_else(|| GENOME_TEMPLATE.to_string())
+    }
+
+    pub fn annotation_template(&self, species: &str) -> String {
+        self.species
+            .get(species)
+            .and_then(|entry| entry.annotation_template.clone())
+            .unwrap_or_else(|| ANNOTATION_TEMPLATE.to_string())
+    }
+}
+
+fn user_file() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("kbrecondo").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_give_the_line_they_are_on() {
+        let path = env::temp_dir().join(format!("kbrecondo_bad_{}.toml", std::process::id()));
+        // the error starts on the first column of line 2
+        fs::write(&path, "[defaults]\nwindw = 5\n").unwrap();
+        let err = Config::read(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(err, Error::Parse { line: Some(2), .. }), "{}", err);
+    }
+}
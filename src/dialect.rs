@@ -0,0 +1,520 @@
+//! Annotation header dialects. Every dialect produces the same
+//! [`AnnotationRecord`], coordinates 1 based and inclusive.
+//!
+//! - legumeinfo: `>medtr.A17.gnm5.ann1_6.MtrunA17Chr1g0146001.1
+//!   gn=MtrunA17Chr1g0146001 chr=MtrunA17Chr1 begin=24712 end=25881 strand=+`
+//! - NCBI RefSeq/GenBank `cds_from_genomic` and `rna_from_genomic`:
+//!   `>lcl|NC_003070.9_cds_NP_171609.1_1 [gene=NAC001] [locus_tag=AT1G01010]
+//!   [protein=NAC domain containing protein 1] [location=join(3760..3913,3996..5630)]`
+//! - Ensembl Plants: `>AT1G01010.1 cds chromosome:TAIR10:1:3760:5630:1
+//!   gene:AT1G01010 gene_symbol:NAC001 description:NAC domain containing protein 1`
+//! - Phytozome: `>Glyma.01G000100.1 pacid=30544134 locus=Glyma.01G000100
+//!   Chr01:27355..28320 forward`. Phytozome transcript files carry no
+//!   coordinates, a `name:begin..end` word (or `chr=`, `begin=`, `end=` and
+//!   `strand=`) has to be added.
+//! - a user regex with the named groups `chr`, `begin`, `end` and `strand`,
+//!   and optionally `id`, `gn`, `locus` and `def`. Other named groups are kept
+//!   as attributes.
+use crate::error::{Error, Result};
+use crate::header::{parse_header, AnnotationRecord, Strand};
+use regex::Regex;
+use std::sync::LazyLock;
+
+pub const DIALECTS: [&str; 5] = ["auto", "legumeinfo", "ncbi", "ensembl", "phytozome"];
+
+/// How annotation headers are written.
+#[derive(Clone, Debug, Default)]
+pub enum Dialect {
+    /// Picked from the first header of each file, see [`Dialect::detect`].
+    #[default]
+    Auto,
+    Legumeinfo,
+    Ncbi,
+    Ensembl,
+    Phytozome,
+    Custom(Regex),
+}
+
+static NCBI_FIELD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^=\[\]\s]+)=([^\]]*)\]").unwrap());
+static LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\S+):(\d+)(?:\.\.|-)(\d+)(?:\(([+-])\))?$").unwrap());
+
+impl Dialect {
+    /// One of [`DIALECTS`].
+    pub fn from_name(name: &str) -> Result<Dialect> {
+        match name.to_lowercase().as_str() {
+            "auto" => Ok(Dialect::Auto),
+            "legumeinfo" => Ok(Dialect::Legumeinfo),
+            "ncbi" | "refseq" | "genbank" => Ok(Dialect::Ncbi),
+            "ensembl" => Ok(Dialect::Ensembl),
+            "phytozome" => Ok(Dialect::Phytozome),
+            _ => Err(Error::config(format!(
+                "unknown header dialect {:?}, expected one of {}",
+                name,
+                DIALECTS.join(", ")
+            ))),
+        }
+    }
+
+    /// A dialect read with `pattern`, which needs the named groups `chr`,
+    /// `begin`, `end` and `strand`.
+    pub fn custom(pattern: &str) -> Result<Dialect> {
+        let regex = Regex::new(pattern)
+            .map_err(|err| Error::config(format!("bad header regex: {}", err)))?;
+        for group in ["chr", "begin", "end", "strand"] {
+            if !regex.capture_names().any(|name| name == Some(group)) {
+                return Err(Error::config(format!(
+                    "the header regex has no (?P<{}>...) group",
+                    group
+                )));
+            }
+        }
+        Ok(Dialect::Custom(regex))
+    }
+
+    /// Guesses the dialect of a header, legumeinfo if nothing else fits.
+    pub fn detect(header: &str) -> Dialect {
+        let mut words = header.split_whitespace();
+        let id = words.next().unwrap_or("").trim_start_matches('>');
+        let words: Vec<&str> = words.collect();
+        if id.starts_with("lcl|") || NCBI_FIELD.is_match(header) {
+            Dialect::Ncbi
+        } else if words.iter().any(|word| ensembl_location(word).is_some()) {
+            Dialect::Ensembl
+        } else if words.iter().any(|word| word.starts_with("chr=")) {
+            Dialect::Legumeinfo
+        } else if words
+            .iter()
+            .any(|word| word.starts_with("pacid=") || word.starts_with("locus="))
+        {
+            Dialect::Phytozome
+        } else {
+            Dialect::Legumeinfo
+        }
+    }
+
+    pub fn parse(&self, header: &str) -> Result<AnnotationRecord> {
+        match self {
+            Dialect::Auto => Dialect::detect(header).parse(header),
+            Dialect::Legumeinfo => AnnotationRecord::from_header(header),
+            Dialect::Ncbi => parse_ncbi(header),
+            Dialect::Ensembl => parse_ensembl(header),
+            Dialect::Phytozome => parse_phytozome(header),
+            Dialect::Custom(regex) => parse_custom(regex, header),
+        }
+    }
+}
+
+// +, -, 1, -1, forward and reverse
+fn parse_strand(id: &str, strand: &str) -> Result<Strand> {
+    match strand {
+        "+" | "1" | "+1" | "forward" => Ok(Strand::Plus),
+        "-" | "-1" | "reverse" => Ok(Strand::Minus),
+        _ => Err(Error::parse(id, format!("bad strand {:?}", strand))),
+    }
+}
+
+fn parse_number(id: &str, what: &str, value: &str) -> Result<usize> {
+    value
+        .parse::<usize>()
+        .map_err(|_| Error::parse(id, format!("{} {:?} is not a number", what, value)))
+}
+
+fn record(
+    id: String,
+    chromosome: String,
+    begin: usize,
+    end: usize,
+    strand: Strand,
+) -> AnnotationRecord {
+    AnnotationRecord {
+        id,
+        gene_name: None,
+        chromosome,
+        begin,
+        end,
+        strand,
+        length: None,
+        locus: None,
+        definition: None,
+        attributes: Vec::new(),
+    }
+}
+
+fn split_id(header: &str) -> (String, &str) {
+    let header = header.trim().trim_start_matches('>');
+    let (id, rest) = header
+        .split_once(char::is_whitespace)
+        .unwrap_or((header, ""));
+    (id.to_string(), rest)
+}
+
+fn parse_ncbi(header: &str) -> Result<AnnotationRecord> {
+    let (id, rest) = split_id(header);
+    // lcl|NC_003070.9_cds_NP_171609.1_1, the accession ends at the first _
+    // after its version
+    let accession = id.trim_start_matches("lcl|");
+    let chromosome = accession
+        .find('.')
+        .and_then(|dot| accession[dot..].find('_').map(|us| &accession[..dot + us]))
+        .ok_or_else(|| Error::parse(&id, "no sequence accession in the id"))?
+        .to_string();
+
+    let mut location = None;
+    let mut gene_name = None;
+    let mut locus = None;
+    let mut definition = None;
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    for field in NCBI_FIELD.captures_iter(rest) {
+        let (key, value) = (field[1].to_string(), field[2].to_string());
+        match key.as_str() {
+            "location" => location = Some(value),
+            "gene" => gene_name = Some(value),
+            "locus_tag" => locus = Some(value),
+            "protein" | "product" if definition.is_none() => definition = Some(value),
+            _ => attributes.push((key, value)),
+        }
+    }
+    let location = location.ok_or_else(|| Error::parse(&id, "missing [location=]"))?;
+    let strand = if location.starts_with("complement(") {
+        Strand::Minus
+    } else {
+        Strand::Plus
+    };
+    let mut positions: Vec<usize> = Vec::new();
+    let spans = location
+        .replace("complement(", "")
+        .replace("join(", "")
+        .replace("order(", "")
+        .replace([')', '<', '>'], "");
+    for span in spans.split(',') {
+        for position in span.split("..") {
+            positions.push(parse_number(&id, "location", position.trim())?);
+        }
+    }
+    let (Some(&begin), Some(&end)) = (positions.iter().min(), positions.iter().max()) else {
+        return Err(Error::parse(&id, "empty [location=]"));
+    };
+
+    let mut annotation = record(id, chromosome, begin, end, strand);
+    annotation.gene_name = gene_name;
+    annotation.locus = locus;
+    annotation.definition = definition;
+    annotation.attributes = attributes;
+    annotation.check()
+}
+
+// chromosome:TAIR10:1:3631:5899:1 -> (1, 3631, 5899, 1)
+fn ensembl_location(word: &str) -> Option<(String, &str, &str, &str)> {
+    let (kind, rest) = word.split_once(':')?;
+    if ![
+        "chromosome",
+        "scaffold",
+        "primary_assembly",
+        "supercontig",
+        "contig",
+    ]
+    .contains(&kind)
+    {
+        return None;
+    }
+    let parts: Vec<&str> = rest.split(':').collect();
+    if parts.len() < 5 {
+        return None;
+    }
+    let n = parts.len();
+    let name = parts[1..n - 3].join(":");
+    Some((name, parts[n - 3], parts[n - 2], parts[n - 1]))
+}
+
+fn parse_ensembl(header: &str) -> Result<AnnotationRecord> {
+    let (id, rest) = split_id(header);
+    let mut location = None;
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut words = rest.split_whitespace();
+    while let Some(word) = words.next() {
+        if location.is_none() {
+            if let Some(found) = ensembl_location(word) {
+                location = Some(found);
+                continue;
+            }
+        }
+        match word.split_once(':') {
+            // the description is free text up to the end of the header
+            Some(("description", value)) => {
+                let mut description = vec![value];
+                description.extend(words.by_ref());
+                fields.push((String::from("description"), description.join(" ")));
+            }
+            Some((key, value)) if !key.is_empty() => {
+                fields.push((key.to_string(), value.to_string()))
+            }
+            // the sequence type after the id, e.g. cds or cdna
+            _ => fields.push((String::from("seq_type"), word.to_string())),
+        }
+    }
+    let (chromosome, begin, end, strand) =
+        location.ok_or_else(|| Error::parse(&id, "missing chromosome:<assembly>:<name>:..."))?;
+    let begin = parse_number(&id, "begin", begin)?;
+    let end = parse_number(&id, "end", end)?;
+    let strand = parse_strand(&id, strand)?;
+
+    let mut annotation = record(id, chromosome, begin, end, strand);
+    let mut symbol = None;
+    for (key, value) in fields {
+        match key.as_str() {
+            "gene" => {
+                annotation.locus = Some(value.clone());
+                annotation.gene_name.get_or_insert(value);
+            }
+            "gene_symbol" => symbol = Some(value),
+            "description" => annotation.definition = Some(value),
+            _ => annotation.attributes.push((key, value)),
+        }
+    }
+    if symbol.is_some() {
+        annotation.gene_name = symbol;
+    }
+    annotation.check()
+}
+
+fn parse_phytozome(header: &str) -> Result<AnnotationRecord> {
+    let (id, rest) = split_id(header);
+    let mut location = None;
+    let mut words = rest.split_whitespace().peekable();
+    let mut key_values: Vec<&str> = Vec::new();
+    while let Some(word) = words.next() {
+        match LOCATION.captures(word) {
+            Some(found) if location.is_none() => {
+                let strand = match found.get(4) {
+                    Some(strand) => strand.as_str(),
+                    None => match words.peek() {
+                        Some(&next) if ["forward", "reverse", "+", "-"].contains(&next) => {
+                            words.next().unwrap_or("+")
+                        }
+                        _ => "+",
+                    },
+                };
+                location = Some((
+                    found[1].to_string(),
+                    found[2].to_string(),
+                    found[3].to_string(),
+                    strand,
+                ));
+            }
+            _ => key_values.push(word),
+        }
+    }
+    let (_, fields) = parse_header(&format!(">{} {}", id, key_values.join(" ")));
+    let get = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    };
+
+    let (chromosome, begin, end, strand) = match location {
+        Some((chromosome, begin, end, strand)) => (
+            chromosome,
+            parse_number(&id, "begin", &begin)?,
+            parse_number(&id, "end", &end)?,
+            parse_strand(&id, strand)?,
+        ),
+        None => match (get("chr"), get("begin"), get("end"), get("strand")) {
+            (Some(chromosome), Some(begin), Some(end), Some(strand)) => (
+                chromosome,
+                parse_number(&id, "begin", &begin)?,
+                parse_number(&id, "end", &end)?,
+                parse_strand(&id, &strand)?,
+            ),
+            _ => {
+                return Err(Error::parse(
+                    &id,
+                    "no coordinates, Phytozome headers need a <chr>:<begin>..<end> word",
+                ))
+            }
+        },
+    };
+
+    let mut annotation = record(id, chromosome, begin, end, strand);
+    annotation.locus = get("locus");
+    annotation.gene_name = get("locus");
+    for (key, value) in fields {
+        match key.as_str() {
+            "locus" | "chr" | "begin" | "end" | "strand" => {}
+            _ => annotation.attributes.push((key, value)),
+        }
+    }
+    annotation.check()
+}
+
+fn parse_custom(regex: &Regex, header: &str) -> Result<AnnotationRecord> {
+    let (first_word, _) = split_id(header);
+    let found = regex
+        .captures(header)
+        .ok_or_else(|| Error::parse(&first_word, "does not match the header regex"))?;
+    let group = |name: &str| found.name(name).map(|m| m.as_str().to_string());
+    let id = group("id").unwrap_or(first_word);
+    let missing = |name: &str| Error::parse(&id, format!("no {} in the header", name));
+    let chromosome = group("chr").ok_or_else(|| missing("chr"))?;
+    let begin = parse_number(
+        &id,
+        "begin",
+        &group("begin").ok_or_else(|| missing("begin"))?,
+    )?;
+    let end = parse_number(&id, "end", &group("end").ok_or_else(|| missing("end"))?)?;
+    let strand = parse_strand(&id, &group("strand").ok_or_else(|| missing("strand"))?)?;
+
+    let mut annotation = record(id, chromosome, begin, end, strand);
+    annotation.gene_name = group("gn");
+    annotation.locus = group("locus");
+    annotation.definition = group("def");
+    for name in regex.capture_names().flatten() {
+        if ["id", "chr", "begin", "end", "strand", "gn", "locus", "def"].contains(&name) {
+            continue;
+        }
+        if let Some(value) = group(name) {
+            annotation.attributes.push((name.to_string(), value));
+        }
+    }
+    annotation.check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGUMEINFO: &str = ">medtr.A17.gnm5.ann1_6.MtrunA17Chr1g0146001.1 gn=MtrunA17Chr1g0146001 chr=MtrunA17Chr1 begin=24712 end=25881 strand=+";
+    const NCBI: &str = ">lcl|NC_003070.9_cds_NP_171609.1_1 [gene=NAC001] [locus_tag=AT1G01010] [protein=NAC domain containing protein 1] [location=join(3760..3913,3996..5630)]";
+    const ENSEMBL: &str = ">AT1G01010.1 cds chromosome:TAIR10:1:3760:5630:1 gene:AT1G01010 gene_symbol:NAC001 description:NAC domain containing protein 1";
+    const PHYTOZOME: &str =
+        ">Glyma.01G000100.1 pacid=30544134 locus=Glyma.01G000100 Chr01:27355..28320 forward";
+
+    fn span(record: &AnnotationRecord) -> (&str, usize, usize, Strand) {
+        (
+            record.chromosome.as_str(),
+            record.begin,
+            record.end,
+            record.strand,
+        )
+    }
+
+    #[test]
+    fn dialects_are_detected() {
+        assert!(matches!(Dialect::detect(LEGUMEINFO), Dialect::Legumeinfo));
+        assert!(matches!(Dialect::detect(NCBI), Dialect::Ncbi));
+        assert!(matches!(Dialect::detect(ENSEMBL), Dialect::Ensembl));
+        assert!(matches!(Dialect::detect(PHYTOZOME), Dialect::Phytozome));
+    }
+
+    #[test]
+    fn ncbi_joins_span_from_the_first_to_the_last_base() {
+        let record = Dialect::Ncbi.parse(NCBI).unwrap();
+        assert_eq!(record.id, "lcl|NC_003070.9_cds_NP_171609.1_1");
+        assert_eq!(span(&record), ("NC_003070.9", 3760, 5630, Strand::Plus));
+        assert_eq!(record.gene_name.as_deref(), Some("NAC001"));
+        assert_eq!(record.locus.as_deref(), Some("AT1G01010"));
+        assert_eq!(
+            record.definition.as_deref(),
+            Some("NAC domain containing protein 1")
+        );
+    }
+
+    #[test]
+    fn ncbi_complement_and_partial_locations() {
+        let header = NCBI.replace(
+            "join(3760..3913,3996..5630)",
+            "complement(join(<6788..7069,7157..>9130))",
+        );
+        let record = Dialect::Ncbi.parse(&header).unwrap();
+        assert_eq!(span(&record), ("NC_003070.9", 6788, 9130, Strand::Minus));
+        // the accession keeps its version, the rest of the id is dropped
+        let header = ">lcl|NW_017189516.1_mrna_XM_003601234.3_12 [location=complement(<100..>250)]";
+        let record = Dialect::Ncbi.parse(header).unwrap();
+        assert_eq!(span(&record), ("NW_017189516.1", 100, 250, Strand::Minus));
+        assert!(Dialect::Ncbi
+            .parse(">lcl|NC_003070_cds [location=1..10]")
+            .is_err());
+        assert!(Dialect::Ncbi.parse(">lcl|NC_003070.9_cds_1").is_err());
+    }
+
+    #[test]
+    fn ensembl_description_runs_to_the_end() {
+        let header = format!("{} [Source:TAIR;Acc:AT1G01010]", ENSEMBL);
+        let record = Dialect::Ensembl.parse(&header).unwrap();
+        assert_eq!(record.id, "AT1G01010.1");
+        assert_eq!(span(&record), ("1", 3760, 5630, Strand::Plus));
+        assert_eq!(record.gene_name.as_deref(), Some("NAC001"));
+        assert_eq!(record.locus.as_deref(), Some("AT1G01010"));
+        assert_eq!(
+            record.definition.as_deref(),
+            Some("NAC domain containing protein 1 [Source:TAIR;Acc:AT1G01010]")
+        );
+        assert_eq!(
+            record.attributes,
+            vec![(String::from("seq_type"), String::from("cds"))]
+        );
+    }
+
+    #[test]
+    fn ensembl_names_can_hold_colons() {
+        let header = ">g1.t1 cdna scaffold:Ga1:Contig:12:1001:2000:-1 gene:g1";
+        let record = Dialect::Ensembl.parse(header).unwrap();
+        assert_eq!(span(&record), ("Contig:12", 1001, 2000, Strand::Minus));
+        // without a symbol the gene is the name
+        assert_eq!(record.gene_name.as_deref(), Some("g1"));
+        assert!(Dialect::Ensembl.parse(">g1.t1 cdna gene:g1").is_err());
+    }
+
+    #[test]
+    fn phytozome_strands() {
+        let record = Dialect::Phytozome.parse(PHYTOZOME).unwrap();
+        assert_eq!(span(&record), ("Chr01", 27355, 28320, Strand::Plus));
+        assert_eq!(record.locus.as_deref(), Some("Glyma.01G000100"));
+        assert_eq!(record.gene_name.as_deref(), Some("Glyma.01G000100"));
+        assert_eq!(
+            record.attributes,
+            vec![(String::from("pacid"), String::from("30544134"))]
+        );
+        let record = Dialect::Phytozome
+            .parse(&PHYTOZOME.replace("forward", "reverse"))
+            .unwrap();
+        assert_eq!(record.strand, Strand::Minus);
+        let record = Dialect::Phytozome
+            .parse(">Glyma.01G000100.1 Chr01:100-200(-)")
+            .unwrap();
+        assert_eq!(span(&record), ("Chr01", 100, 200, Strand::Minus));
+        // transcript files need the coordinates added
+        let record = Dialect::Phytozome
+            .parse(">Glyma.01G000100.1 chr=Chr02 begin=5 end=50 strand=-")
+            .unwrap();
+        assert_eq!(span(&record), ("Chr02", 5, 50, Strand::Minus));
+        assert!(Dialect::Phytozome
+            .parse(">Glyma.01G000100.1 pacid=30544134")
+            .is_err());
+    }
+
+    #[test]
+    fn custom_regex_groups() {
+        assert!(Dialect::custom(r"(?P<chr>\w+):(?P<begin>\d+)-(?P<end>\d+)").is_err());
+        assert!(Dialect::custom(r"(?P<chr>\w+").is_err());
+        let dialect = Dialect::custom(
+            r"^>(?P<id>\S+) (?P<chr>\w+):(?P<begin>\d+)-(?P<end>\d+)\((?P<strand>[+-])\) (?P<gn>\S+) (?P<biotype>\S+)",
+        )
+        .unwrap();
+        let record = dialect
+            .parse(">tx1 chr2:10-50(-) GENE1 protein_coding")
+            .unwrap();
+        assert_eq!(record.id, "tx1");
+        assert_eq!(span(&record), ("chr2", 10, 50, Strand::Minus));
+        assert_eq!(record.gene_name.as_deref(), Some("GENE1"));
+        assert_eq!(
+            record.attributes,
+            vec![(String::from("biotype"), String::from("protein_coding"))]
+        );
+        assert!(dialect.parse(">tx1 chr2 10 50").is_err());
+        // begin=0 is caught as in every dialect
+        assert!(dialect.parse(">tx1 chr2:0-50(+) GENE1 x").is_err());
+    }
+}
@@ -0,0 +1,639 @@
+//! Whether a motif is found more often around a set of genes than expected.
+//!
+//! The hits in the windows of the target genes are compared with those of
+//! a background: the windows of other genes, or sequences generated from
+//! the target windows with the same composition (shuffled, or from a Markov
+//! model of them). Per motif this gives the expected hits, the fold
+//! enrichment and a p-value, [`adjust`]ed across the motifs.
+//!
+//! The hypergeometric test counts genes with at least one hit among all
+//! target and background genes. The binomial test counts hits, every
+//! position a motif can start at being one trial with the background rate.
+use crate::error::{Error, Result};
+use crate::extract::reverse_complement;
+use crate::fasta::Genome;
+use crate::header::{AnnotationRecord, Strand};
+use crate::matcher;
+use crate::search::{search_genome, GeneHits};
+use crate::window::build_window;
+use std::collections::HashSet;
+
+/// What was found in a set of windows or sequences.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub genes: usize,
+    pub genes_with_hits: usize,
+    pub hits: usize,
+    /// Positions an occurrence of the motif could start at.
+    pub positions: usize,
+}
+
+impl Counts {
+    /// Counts of a search with a pattern `pattern_len` long.
+    pub fn of(results: &[GeneHits], pattern_len: usize) -> Counts {
+        let mut counts = Counts::default();
+        for gene in results {
+            counts.add(gene.hits.len(), gene.window.len(), pattern_len);
+        }
+        counts
+    }
+
+    /// Counts of `pattern` in each of `seqs`, which have to be lowercase.
+    pub fn search(seqs: &[Vec<u8>], pattern: &str, mismatches: usize) -> Counts {
+        let pattern = pattern.to_lowercase();
+        let mut counts = Counts::default();
+        for seq in seqs {
+            let hits = matcher::find_all(seq, pattern.as_bytes(), mismatches).len();
+            counts.add(hits, seq.len(), pattern.len());
+        }
+        counts
+    }
+
+    fn add(&mut self, hits: usize, len: usize, pattern_len: usize) {
+        self.genes += 1;
+        if hits > 0 {
+            self.genes_with_hits += 1;
+        }
+        self.hits += hits;
+        self.positions += (len + 1).saturating_sub(pattern_len);
+    }
+}
+
+/// The windows of `annotation`, lowercase and turned to the strand of their
+/// gene. Records on chromosomes missing from the genome are skipped.
+pub fn window_sequences(
+    genome: &Genome,
+    annotation: &[AnnotationRecord],
+    size: usize,
+) -> Vec<Vec<u8>> {
+    let mut seqs = Vec::new();
+    for record in annotation {
+        let Some(chrom) = genome.get(&record.chromosome) else {
+            continue;
+        };
+        let window = build_window(record.begin, record.end, size, chrom.seq.len());
+        let mut seq = chrom.seq[window.left..window.right].to_ascii_lowercase();
+        if record.strand == Strand::Minus {
+            seq = reverse_complement(&seq);
+        }
+        seqs.push(seq.into_bytes());
+    }
+    seqs
+}
+
+/// A small seeded random number generator (SplitMix64), so backgrounds can
+/// be generated again with the same seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// `seq` with its bases in random order, keeping the composition.
+pub fn shuffled(seq: &[u8], rng: &mut Rng) -> Vec<u8> {
+    let mut seq = seq.to_vec();
+    for i in (1..seq.len()).rev() {
+        seq.swap(i, rng.below(i + 1));
+    }
+    seq
+}
+
+const BASES: [u8; 4] = *b"acgt";
+
+fn base_index(base: u8) -> Option<usize> {
+    BASES.iter().position(|&b| b == base)
+}
+
+/// Markov chain of the bases of some sequences, each base depending on the
+/// `order` bases before it. Characters other than a, c, g and t break the
+/// chain.
+#[derive(Clone, Debug)]
+pub struct MarkovModel {
+    pub order: usize,
+    /// Per context of `order` bases (a base 4 number), how often each base
+    /// followed it, plus one.
+    counts: Vec<[usize; 4]>,
+    /// How often each context started a run of bases, plus one.
+    starts: Vec<usize>,
+}
+
+impl MarkovModel {
+    pub fn train(seqs: &[Vec<u8>], order: usize) -> Result<MarkovModel> {
+        if order > 8 {
+            return Err(Error::config("the Markov order can be at most 8"));
+        }
+        let contexts = 4usize.pow(order as u32);
+        let mut model = MarkovModel {
+            order,
+            counts: vec![[1; 4]; contexts],
+            starts: vec![1; contexts],
+        };
+        for seq in seqs {
+            // bases of the current run, and the context of its last ones
+            let mut run = 0;
+            let mut context = 0;
+            for &base in seq {
+                let Some(base) = base_index(base) else {
+                    run = 0;
+                    context = 0;
+                    continue;
+                };
+                if run >= order {
+                    model.counts[context][base] += 1;
+                }
+                context = (context * 4 + base) % contexts;
+                run += 1;
+                if run == order {
+                    model.starts[context] += 1;
+                }
+            }
+        }
+        Ok(model)
+    }
+
+    /// A new sequence `len` bases long.
+    pub fn generate(&self, len: usize, rng: &mut Rng) -> Vec<u8> {
+        let contexts = self.starts.len();
+        let mut context = pick(&self.starts, rng);
+        let mut seq: Vec<u8> = Vec::with_capacity(len + self.order);
+        // the first context spelled out, oldest base first
+        for i in (0..self.order).rev() {
+            seq.push(BASES[(context / 4usize.pow(i as u32)) % 4]);
+        }
+        while seq.len() < len {
+            let base = pick(&self.counts[context], rng);
+            seq.push(BASES[base]);
+            context = (context * 4 + base) % contexts;
+        }
+        seq.truncate(len);
+        seq
+    }
+}
+
+// an index drawn with the weights of `weights`
+fn pick(weights: &[usize], rng: &mut Rng) -> usize {
+    let total: usize = weights.iter().sum();
+    let mut left = rng.below(total);
+    for (i, &weight) in weights.iter().enumerate() {
+        if left < weight {
+            return i;
+        }
+        left -= weight;
+    }
+    weights.len() - 1
+}
+
+/// How the p-value of a motif is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Test {
+    /// Genes with hits among all target and background genes.
+    Hypergeometric,
+    /// Hits in the target windows at the background rate per position,
+    /// counting one more background hit so the rate is never 0.
+    Binomial,
+}
+
+impl Test {
+    /// `hypergeometric` or `binomial`.
+    pub fn parse(test: &str) -> Result<Test> {
+        match test {
+            "hypergeometric" | "hyper" => Ok(Test::Hypergeometric),
+            "binomial" | "binom" => Ok(Test::Binomial),
+            _ => Err(Error::config(format!(
+                "unknown test {:?}, expected hypergeometric or binomial",
+                test
+            ))),
+        }
+    }
+}
+
+/// Multiple testing correction across motifs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Correction {
+    /// Benjamini-Hochberg false discovery rate.
+    Bh,
+    Bonferroni,
+    None,
+}
+
+impl Correction {
+    /// `bh` (or `fdr`), `bonferroni` or `none`.
+    pub fn parse(correction: &str) -> Result<Correction> {
+        match correction {
+            "bh" | "fdr" => Ok(Correction::Bh),
+            "bonferroni" => Ok(Correction::Bonferroni),
+            "none" => Ok(Correction::None),
+            _ => Err(Error::config(format!(
+                "unknown correction {:?}, expected bh, bonferroni or none",
+                correction
+            ))),
+        }
+    }
+}
+
+/// The result for one motif.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enrichment {
+    /// Name of the motif, e.g. its FASTA header.
+    pub motif: String,
+    pub pattern: String,
+    pub target: Counts,
+    pub background: Counts,
+    /// Hits expected in the target windows at the background rate.
+    pub expected_hits: f64,
+    /// Observed over expected hits, `None` when none are expected.
+    pub fold: Option<f64>,
+    /// Target genes expected to have a hit at the background rate.
+    pub expected_genes: f64,
+    pub p_value: f64,
+    /// Set by [`adjust`].
+    pub p_adjusted: f64,
+}
+
+impl Enrichment {
+    pub fn new(
+        motif: &str,
+        pattern: &str,
+        target: Counts,
+        background: Counts,
+        test: Test,
+    ) -> Enrichment {
+        let rate = match background.positions {
+            0 => 0.0,
+            positions => background.hits as f64 / positions as f64,
+        };
+        let expected_hits = rate * target.positions as f64;
+        let expected_genes = match background.genes {
+            0 => 0.0,
+            genes => background.genes_with_hits as f64 / genes as f64 * target.genes as f64,
+        };
+        let p_value = match test {
+            Test::Hypergeometric => hypergeometric_upper(
+                target.genes_with_hits,
+                target.genes,
+                target.genes_with_hits + background.genes_with_hits,
+                target.genes + background.genes,
+            ),
+            // one pseudocount, so a motif absent from the background is not
+            // certain to be enriched by any single hit
+            Test::Binomial => binomial_upper(
+                target.hits,
+                target.positions,
+                (background.hits + 1) as f64 / (background.positions + 1) as f64,
+            ),
+        };
+        Enrichment {
+            motif: motif.to_string(),
+            pattern: pattern.to_string(),
+            target,
+            background,
+            expected_hits,
+            fold: match expected_hits > 0.0 {
+                true => Some(target.hits as f64 / expected_hits),
+                false => None,
+            },
+            expected_genes,
+            p_value,
+            p_adjusted: p_value,
+        }
+    }
+}
+
+/// Sets the adjusted p-values of `results`.
+pub fn adjust(results: &mut [Enrichment], correction: Correction) {
+    let p_values: Vec<f64> = results.iter().map(|result| result.p_value).collect();
+    for (result, p) in results.iter_mut().zip(adjusted(&p_values, correction)) {
+        result.p_adjusted = p;
+    }
+}
+
+/// `p_values` corrected for being tested together.
+pub fn adjusted(p_values: &[f64], correction: Correction) -> Vec<f64> {
+    let m = p_values.len() as f64;
+    match correction {
+        Correction::None => p_values.to_vec(),
+        Correction::Bonferroni => p_values.iter().map(|p| (p * m).min(1.0)).collect(),
+        Correction::Bh => {
+            let mut order: Vec<usize> = (0..p_values.len()).collect();
+            order.sort_by(|&a, &b| p_values[a].total_cmp(&p_values[b]));
+            let mut adjusted = vec![1.0; p_values.len()];
+            let mut smallest: f64 = 1.0;
+            for (rank, &i) in order.iter().enumerate().rev() {
+                smallest = smallest.min(p_values[i] * m / (rank + 1) as f64);
+                adjusted[i] = smallest;
+            }
+            adjusted
+        }
+    }
+}
+
+/// What the target genes are compared with.
+#[derive(Clone, Debug)]
+pub enum Background {
+    /// Every other gene of the annotation.
+    All,
+    /// These genes, leaving out the targets.
+    Genes(Vec<AnnotationRecord>),
+    /// Each target window shuffled `samples` times.
+    Shuffle { samples: usize },
+    /// `samples` sequences per target window from a Markov model of `order`
+    /// of the target windows.
+    Markov { samples: usize, order: usize },
+}
+
+/// Settings of [`enrich`].
+#[derive(Clone, Debug)]
+pub struct EnrichOptions {
+    /// Bases searched on each side of a gene.
+    pub window: usize,
+    pub mismatches: usize,
+    pub background: Background,
+    /// Seed of the shuffle and Markov backgrounds.
+    pub seed: u64,
+    pub test: Test,
+    pub correction: Correction,
+}
+
+/// Tests every `(name, pattern)` of `motifs` for enrichment around the
+/// `targets` of `annotation`, p-values adjusted across the motifs.
+pub fn enrich(
+    genome: &Genome,
+    annotation: &[AnnotationRecord],
+    targets: &[AnnotationRecord],
+    motifs: &[(String, String)],
+    options: &EnrichOptions,
+) -> Result<Vec<Enrichment>> {
+    if targets.is_empty() {
+        return Err(Error::config("there are no target genes"));
+    }
+    let window = options.window;
+    let target_ids: HashSet<&str> = targets.iter().map(|record| record.id.as_str()).collect();
+    let others = |genes: &[AnnotationRecord]| -> Vec<AnnotationRecord> {
+        genes
+            .iter()
+            .filter(|record| !target_ids.contains(record.id.as_str()))
+            .cloned()
+            .collect()
+    };
+    let mut rng = Rng::new(options.seed);
+    // background genes, or else generated lowercase sequences
+    let (genes, seqs) = match &options.background {
+        Background::All => (others(annotation), Vec::new()),
+        Background::Genes(genes) => (others(genes), Vec::new()),
+        Background::Shuffle { samples } => {
+            let windows = window_sequences(genome, targets, window);
+            let mut seqs = Vec::new();
+            for _ in 0..*samples {
+                for seq in &windows {
+                    seqs.push(shuffled(seq, &mut rng));
+                }
+            }
+            (Vec::new(), seqs)
+        }
+        Background::Markov { samples, order } => {
+            let windows = window_sequences(genome, targets, window);
+            let model = MarkovModel::train(&windows, *order)?;
+            let mut seqs = Vec::new();
+            for _ in 0..*samples {
+                for seq in &windows {
+                    seqs.push(model.generate(seq.len(), &mut rng));
+                }
+            }
+            (Vec::new(), seqs)
+        }
+    };
+    if genes.is_empty() && seqs.is_empty() {
+        return Err(Error::config("the background is empty"));
+    }
+
+    let mut results: Vec<Enrichment> = Vec::new();
+    for (motif, pattern) in motifs {
+        let found = search_genome(genome, targets, window, pattern, options.mismatches);
+        let target = Counts::of(&found, pattern.len());
+        let background = match seqs.is_empty() {
+            true => Counts::of(
+                &search_genome(genome, &genes, window, pattern, options.mismatches),
+                pattern.len(),
+            ),
+            false => Counts::search(&seqs, pattern, options.mismatches),
+        };
+        results.push(Enrichment::new(
+            motif,
+            pattern,
+            target,
+            background,
+            options.test,
+        ));
+    }
+    adjust(&mut results, options.correction);
+    Ok(results)
+}
+
+/// Natural log of the gamma function (Lanczos approximation).
+pub fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // reflection
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut a = G[0];
+    let t = x + 7.5;
+    for (i, g) in G.iter().enumerate().skip(1) {
+        a += g / (x + i as f64);
+    }
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+fn ln_choose(n: usize, k: usize) -> f64 {
+    ln_gamma(n as f64 + 1.0) - ln_gamma(k as f64 + 1.0) - ln_gamma((n - k) as f64 + 1.0)
+}
+
+/// Probability of drawing `k` or more marked items when drawing `n` of
+/// `total` items of which `marked` are marked.
+pub fn hypergeometric_upper(k: usize, n: usize, marked: usize, total: usize) -> f64 {
+    if k == 0 {
+        return 1.0;
+    }
+    let high = n.min(marked);
+    let low = k.max((n + marked).saturating_sub(total));
+    if low > high || n > total {
+        return 0.0;
+    }
+    let all = ln_choose(total, n);
+    let p: f64 = (low..=high)
+        .map(|i| (ln_choose(marked, i) + ln_choose(total - marked, n - i) - all).exp())
+        .sum();
+    p.min(1.0)
+}
+
+/// Probability of `k` or more successes in `n` trials with probability `p`.
+pub fn binomial_upper(k: usize, n: usize, p: f64) -> f64 {
+    if k == 0 {
+        return 1.0;
+    }
+    if k > n || p <= 0.0 {
+        return 0.0;
+    }
+    if p >= 1.0 {
+        return 1.0;
+    }
+    incomplete_beta(k as f64, (n - k + 1) as f64, p)
+}
+
+// regularized incomplete beta function I_x(a, b)
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        (front * beta_fraction(a, b, x) / a).clamp(0.0, 1.0)
+    } else {
+        (1.0 - front * beta_fraction(b, a, 1.0 - x) / b).clamp(0.0, 1.0)
+    }
+}
+
+// continued fraction of the incomplete beta function (modified Lentz)
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..100_000 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        let aa = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + aa * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1.0 + aa / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + aa * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1.0 + aa / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        let step = d * c;
+        h *= step;
+        if (step - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1e-300)
+    }
+
+    #[test]
+    fn hypergeometric_tail() {
+        // 3 or more of 5 draws from 10 items with 4 marked:
+        // (C(4,3) C(6,2) + C(4,4) C(6,1)) / C(10,5) = (60 + 6) / 252
+        assert!(close(hypergeometric_upper(3, 5, 4, 10), 66.0 / 252.0));
+        assert_eq!(hypergeometric_upper(0, 5, 4, 10), 1.0);
+        assert_eq!(hypergeometric_upper(5, 5, 4, 10), 0.0);
+    }
+
+    #[test]
+    fn binomial_tail() {
+        // 2 or more in 3 trials at 0.5: 4 of 8 outcomes
+        assert!(close(binomial_upper(2, 3, 0.5), 0.5));
+        // 1 or more in 10 trials at 0.1
+        assert!(close(binomial_upper(1, 10, 0.1), 1.0 - 0.9f64.powi(10)));
+        assert!(close(binomial_upper(10, 10, 0.1), 1e-10));
+    }
+
+    #[test]
+    fn binomial_without_background_hits_is_not_zero() {
+        let target = Counts {
+            genes: 1,
+            genes_with_hits: 1,
+            hits: 1,
+            positions: 1000,
+        };
+        let background = Counts {
+            genes: 10,
+            genes_with_hits: 0,
+            hits: 0,
+            positions: 10000,
+        };
+        let result = Enrichment::new("m", "acgtacgt", target, background, Test::Binomial);
+        // 1 or more in 1000 trials at 1 / 10001
+        let want = 1.0 - (1.0 - 1.0 / 10001.0f64).powi(1000);
+        assert!(close(result.p_value, want), "{}", result.p_value);
+        assert_eq!(result.fold, None);
+    }
+
+    #[test]
+    fn benjamini_hochberg() {
+        let p = [0.01, 0.04, 0.03, 0.5];
+        let bh = adjusted(&p, Correction::Bh);
+        for (got, want) in bh
+            .iter()
+            .zip([0.04, 0.04 * 4.0 / 3.0, 0.04 * 4.0 / 3.0, 0.5])
+        {
+            assert!(close(*got, want), "{} != {}", got, want);
+        }
+        assert_eq!(adjusted(&p, Correction::Bonferroni)[1], 0.16);
+    }
+
+    #[test]
+    fn generated_backgrounds_keep_the_length_and_composition() {
+        let seqs = vec![b"aaccggttacgtacgtnnacgt".to_vec()];
+        let mut rng = Rng::new(7);
+        let mut shuffle = shuffled(&seqs[0], &mut rng);
+        shuffle.sort();
+        let mut sorted = seqs[0].clone();
+        sorted.sort();
+        assert_eq!(shuffle, sorted);
+
+        let model = MarkovModel::train(&seqs, 2).unwrap();
+        let generated = model.generate(100, &mut rng);
+        assert_eq!(generated.len(), 100);
+        assert!(generated.iter().all(|base| b"acgt".contains(base)));
+        assert_eq!(
+            MarkovModel::train(&seqs, 0)
+                .unwrap()
+                .generate(3, &mut rng)
+                .len(),
+            3
+        );
+    }
+}
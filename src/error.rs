@@ -0,0 +1,143 @@
+//! Errors returned by the library, with enough context (file, line, record)
+//! for the binary to tell the user what to fix.
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A header, number or line could not be understood.
+    Parse {
+        path: Option<PathBuf>,
+        line: Option<usize>,
+        record: String,
+        message: String,
+    },
+    /// The input parsed but is inconsistent, e.g. a sequence that does not
+    /// match the length in its header.
+    Validation {
+        path: Option<PathBuf>,
+        line: Option<usize>,
+        record: String,
+        message: String,
+    },
+    /// Bad arguments or settings.
+    Config(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &Path, source: io::Error) -> Error {
+        Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn parse(record: &str, message: impl Into<String>) -> Error {
+        Error::Parse {
+            path: None,
+            line: None,
+            record: record.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn validation(record: &str, message: impl Into<String>) -> Error {
+        Error::Validation {
+            path: None,
+            line: None,
+            record: record.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn config(message: impl Into<String>) -> Error {
+        Error::Config(message.into())
+    }
+
+    /// Attaches the file and line a parse or validation error came from.
+    pub fn at(self, at_path: &Path, at_line: usize) -> Error {
+        self.in_file(at_path).at_line(at_line)
+    }
+
+    /// Attaches the file a parse or validation error came from.
+    pub fn in_file(mut self, at_path: &Path) -> Error {
+        if let Error::Parse { path, .. } | Error::Validation { path, .. } = &mut self {
+            *path = Some(at_path.to_path_buf());
+        }
+        self
+    }
+
+    /// Attaches the line a parse or validation error came from.
+    pub fn at_line(mut self, at_line: usize) -> Error {
+        if let Error::Parse { line, .. } | Error::Validation { line, .. } = &mut self {
+            *line = Some(at_line);
+        }
+        self
+    }
+
+    /// Exit status of the binary for this kind of error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Io { .. } => 3,
+            Error::Parse { .. } => 4,
+            Error::Validation { .. } => 5,
+        }
+    }
+}
+
+fn write_location(
+    f: &mut fmt::Formatter,
+    path: &Option<PathBuf>,
+    line: &Option<usize>,
+) -> fmt::Result {
+    if let Some(path) = path {
+        write!(f, "{}:", path.display())?;
+        if let Some(line) = line {
+            write!(f, "{}:", line)?;
+        }
+        write!(f, " ")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Parse {
+                path,
+                line,
+                record,
+                message,
+            } => {
+                write_location(f, path, line)?;
+                write!(f, "could not parse {:?}: {}", record, message)
+            }
+            Error::Validation {
+                path,
+                line,
+                record,
+                message,
+            } => {
+                write_location(f, path, line)?;
+                write!(f, "{}: {}", record, message)
+            }
+            Error::Config(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Sequences of the searched windows, and of every hit with some flanking
+//! context, for the output columns or written as FASTA. Sequences are
+//! turned to the strand of their gene, the coordinates in the headers are 1
+//! based and inclusive on the forward strand of the chromosome.
+use crate::fasta::Chromosome;
+use crate::header::Strand;
+use crate::search::{GeneHits, Hit};
+use std::io::{self, Write};
+
+/// Bases per FASTA line.
+const LINE_WIDTH: usize = 60;
+
+/// Reverse complement of `seq`, keeping the case. IUPAC codes are
+/// complemented too, anything else is kept as it is.
+pub fn reverse_complement(seq: &str) -> String {
+    seq.chars()
+        .rev()
+        .map(|nuc| {
+            let complement = match nuc.to_ascii_lowercase() {
+                'a' => 't',
+                'c' => 'g',
+                'g' => 'c',
+                't' | 'u' => 'a',
+                'r' => 'y',
+                'y' => 'r',
+                'k' => 'm',
+                'm' => 'k',
+                'b' => 'v',
+                'v' => 'b',
+                'd' => 'h',
+                'h' => 'd',
+                other => other,
+            };
+            match nuc.is_ascii_uppercase() {
+                true => complement.to_ascii_uppercase(),
+                false => complement,
+            }
+        })
+        .collect()
+}
+
+fn write_record<W: Write>(out: &mut W, header: &str, seq: &str) -> io::Result<()> {
+    writeln!(out, ">{}", header)?;
+    for line in seq.as_bytes().chunks(LINE_WIDTH) {
+        out.write_all(line)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn header(gene: &GeneHits, name: &str, begin: usize, end: usize) -> String {
+    let record = &gene.record;
+    let mut header = format!(
+        "{}{} chr={} begin={} end={} strand={}",
+        record.id, name, record.chromosome, begin, end, record.strand
+    );
+    if let Some(gene_name) = &record.gene_name {
+        header.push_str(&format!(" gn={}", gene_name));
+    }
+    header
+}
+
+/// Writes the window of `gene`.
+pub fn write_window_fasta<W: Write>(
+    out: &mut W,
+    chrom: &Chromosome,
+    gene: &GeneHits,
+) -> io::Result<()> {
+    let window = &gene.window;
+    let mut seq = chrom.seq[window.left..window.right].to_string();
+    if gene.record.strand == Strand::Minus {
+        seq = reverse_complement(&seq);
+    }
+    let mut header = header(gene, "_window", window.left + 1, window.right);
+    header.push_str(&format!(" hits={}", gene.hits.len()));
+    write_record(out, &header, &seq)
+}
+
+/// The sequence of a hit and its flanks, read in the direction of its
+/// gene: on the `-` strand `left` is the reverse complement of the bases
+/// after the hit on the chromosome.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HitContext {
+    pub left: String,
+    pub matched: String,
+    pub right: String,
+    /// Offsets `begin..end` of the flanks and the hit together, on the
+    /// forward strand.
+    pub begin: usize,
+    pub end: usize,
+}
+
+impl HitContext {
+    /// `left` and `right` bases of context around `hit`, clipped to the
+    /// chromosome.
+    pub fn new(
+        chrom: &Chromosome,
+        hit: &Hit,
+        pattern_len: usize,
+        strand: Strand,
+        left: usize,
+        right: usize,
+    ) -> HitContext {
+        // flanks on the forward strand
+        let (before, after) = match strand {
+            Strand::Plus => (left, right),
+            Strand::Minus => (right, left),
+        };
+        let seq = &chrom.seq;
+        let hit_end = (hit.location + pattern_len).min(seq.len());
+        let begin = hit.location.saturating_sub(before);
+        let end = (hit_end + after).min(seq.len());
+        let before = &seq[begin..hit.location];
+        let matched = &seq[hit.location..hit_end];
+        let after = &seq[hit_end..end];
+        match strand {
+            Strand::Plus => HitContext {
+                left: before.to_string(),
+                matched: matched.to_string(),
+                right: after.to_string(),
+                begin,
+                end,
+            },
+            Strand::Minus => HitContext {
+                left: reverse_complement(after),
+                matched: reverse_complement(matched),
+                right: reverse_complement(before),
+                begin,
+                end,
+            },
+        }
+    }
+
+    /// Contexts of every hit of `gene`.
+    pub fn all(
+        chrom: &Chromosome,
+        gene: &GeneHits,
+        pattern_len: usize,
+        left: usize,
+        right: usize,
+    ) -> Vec<HitContext> {
+        gene.hits
+            .iter()
+            .map(|hit| HitContext::new(chrom, hit, pattern_len, gene.record.strand, left, right))
+            .collect()
+    }
+}
+
+/// Writes every hit of `gene` with `flank` bases on both sides, clipped to
+/// the chromosome. The hit is upper case and the flanks lower case.
+pub fn write_hit_fasta<W: Write>(
+    out: &mut W,
+    chrom: &Chromosome,
+    gene: &GeneHits,
+    pattern_len: usize,
+    flank: usize,
+) -> io::Result<()> {
+    for (n, hit) in gene.hits.iter().enumerate() {
+        let context = HitContext::new(chrom, hit, pattern_len, gene.record.strand, flank, flank);
+        let seq = format!(
+            "{}{}{}",
+            context.left.to_ascii_lowercase(),
+            context.matched.to_ascii_uppercase(),
+            context.right.to_ascii_lowercase()
+        );
+        let hit_end = hit.location + pattern_len;
+        let mut header = header(
+            gene,
+            &format!("_hit{}", n + 1),
+            context.begin + 1,
+            context.end,
+        );
+        header.push_str(&format!(
+            " hit={}..{} mismatches={}",
+            hit.location + 1,
+            hit_end,
+            hit.mismatches
+        ));
+        write_record(out, &header, &seq)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chrom() -> Chromosome {
+        Chromosome {
+            header: String::from(">chr1"),
+            name: String::from("chr1"),
+            seq: String::from("aaCCtacgtGGttt"),
+        }
+    }
+
+    #[test]
+    fn flanks_follow_the_gene_strand() {
+        let hit = Hit {
+            location: 4,
+            mismatches: 0,
+        };
+        let plus = HitContext::new(&chrom(), &hit, 5, Strand::Plus, 2, 3);
+        assert_eq!(
+            (
+                plus.left.as_str(),
+                plus.matched.as_str(),
+                plus.right.as_str()
+            ),
+            ("CC", "tacgt", "GGt")
+        );
+        assert_eq!((plus.begin, plus.end), (2, 12));
+        // upstream of a - strand gene is to the right on the chromosome
+        let minus = HitContext::new(&chrom(), &hit, 5, Strand::Minus, 2, 3);
+        assert_eq!(
+            (
+                minus.left.as_str(),
+                minus.matched.as_str(),
+                minus.right.as_str()
+            ),
+            ("CC", "acgta", "GGt")
+        );
+        assert_eq!((minus.begin, minus.end), (1, 11));
+    }
+
+    #[test]
+    fn flanks_are_clipped_to_the_chromosome() {
+        let hit = Hit {
+            location: 0,
+            mismatches: 0,
+        };
+        let context = HitContext::new(&chrom(), &hit, 2, Strand::Plus, 5, 1);
+        assert_eq!(context.left, "");
+        assert_eq!(context.matched, "aa");
+        assert_eq!(context.right, "C");
+        assert_eq!(reverse_complement("ACgtN"), "NacGT");
+    }
+}
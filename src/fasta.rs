@@ -0,0 +1,194 @@
+//! Readers for the genome and annotation FASTAs, gzipped or not, and the
+//! plain FASTA holding a search sequence.
+use crate::dialect::Dialect;
+use crate::error::{Error, Result};
+use crate::header::{chromosome_name, get_element};
+use crate::AnnotationRecord;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// One record of a FASTA file.
+pub struct FastaRecord {
+    /// Line of the header in the file, 1 based.
+    pub line: usize,
+    /// Full header line, including the leading `>`.
+    pub header: String,
+    pub seq: String,
+}
+
+/// One sequence of the genome FASTA.
+pub struct Chromosome {
+    /// Full header line, including the leading `>`.
+    pub header: String,
+    /// Chromosome name from the `acc=` field or else the first word of the
+    /// header, matched against the chromosome of the annotation.
+    pub name: String,
+    pub seq: String,
+}
+
+impl Chromosome {
+    fn from_record(record: FastaRecord) -> Result<Chromosome> {
+        let FastaRecord { header, seq, .. } = record;
+        let name = chromosome_name(&header);
+        if name.is_empty() {
+            return Err(Error::parse(&header, "no chromosome name"));
+        }
+        if let Some(chrom_len) = get_element(&header, "len") {
+            let chrom_len_num = chrom_len
+                .parse::<usize>()
+                .map_err(|_| Error::parse(&header, format!("len={} is not a number", chrom_len)))?;
+            if seq.len() != chrom_len_num {
+                return Err(Error::validation(
+                    &name,
+                    format!(
+                        "sequence is {} bases long but the header says len={}",
+                        seq.len(),
+                        chrom_len_num
+                    ),
+                ));
+            }
+        }
+        Ok(Chromosome { header, name, seq })
+    }
+}
+
+/// A decoded genome.
+pub struct Genome {
+    pub chromosomes: Vec<Chromosome>,
+}
+
+impl Genome {
+    /// Reads a genome FASTA, gzipped or not.
+    pub fn read<P>(filename: P) -> Result<Genome>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = filename.as_ref();
+        Genome::from_fasta(read_fasta(filename)?).map_err(|err| err.in_file(filename))
+    }
+
+    /// Builds a genome from FASTA records. The sequence has to match the
+    /// `len=` field of its header if there is one.
+    pub fn from_fasta(fasta: Vec<FastaRecord>) -> Result<Genome> {
+        let mut chromosomes: Vec<Chromosome> = Vec::new();
+        for record in fasta {
+            let line = record.line;
+            chromosomes.push(Chromosome::from_record(record).map_err(|err| err.at_line(line))?);
+        }
+        Ok(Genome { chromosomes })
+    }
+
+    /// Looks a chromosome up by its name.
+    pub fn get(&self, name: &str) -> Option<&Chromosome> {
+        self.chromosomes.iter().find(|chrom| chrom.name == name)
+    }
+}
+
+/// Reads the headers of an annotation FASTA (cds, mrna, ...) written in
+/// `dialect`, the sequences themselves are not needed.
+pub fn read_annotation<P>(filename: P, dialect: &Dialect) -> Result<Vec<AnnotationRecord>>
+where
+    P: AsRef<Path>,
+{
+    let filename = filename.as_ref();
+    let fasta = read_fasta(filename)?;
+    let dialect = match (dialect, fasta.first()) {
+        (Dialect::Auto, Some(first)) => Dialect::detect(&first.header),
+        _ => dialect.clone(),
+    };
+    fasta
+        .iter()
+        .map(|record| {
+            dialect
+                .parse(&record.header)
+                .map_err(|err| err.at(filename, record.line))
+        })
+        .collect()
+}
+
+/// Reads a FASTA, gunzipping it if it starts with the gzip magic bytes.
+/// Records are returned in file order.
+pub fn read_fasta<P>(filename: P) -> Result<Vec<FastaRecord>>
+where
+    P: AsRef<Path>,
+{
+    let filename = filename.as_ref();
+    let file = File::open(filename).map_err(|err| Error::io(filename, err))?;
+    let mut reader = BufReader::new(file);
+    let magic = reader.fill_buf().map_err(|err| Error::io(filename, err))?;
+    let fasta = if magic.starts_with(&[0x1f, 0x8b]) {
+        read_records(filename, MultiGzDecoder::new(reader))?
+    } else {
+        read_records(filename, reader)?
+    };
+    Ok(fasta)
+}
+
+/// Reads a plain FASTA holding a single search sequence, returns its header
+/// and sequence.
+pub fn read_search_fasta_single<P>(filename: P) -> Result<(String, String)>
+where
+    P: AsRef<Path>,
+{
+    let filename = filename.as_ref();
+    let file = File::open(filename).map_err(|err| Error::io(filename, err))?;
+    let mut fasta = read_records(filename, file)?;
+    if fasta.len() != 1 || fasta[0].seq.is_empty() {
+        let message = format!("expected one sequence, found {} records", fasta.len());
+        return Err(Error::validation("search fasta", message).in_file(filename));
+    }
+    let record = fasta.remove(0);
+    Ok((record.header, record.seq))
+}
+
+/// Reads a plain FASTA holding one or more search sequences, returns the
+/// header and sequence of each.
+pub fn read_search_fasta<P>(filename: P) -> Result<Vec<(String, String)>>
+where
+    P: AsRef<Path>,
+{
+    let filename = filename.as_ref();
+    let file = File::open(filename).map_err(|err| Error::io(filename, err))?;
+    let fasta = read_records(filename, file)?;
+    if fasta.is_empty() {
+        return Err(Error::validation("search fasta", "no sequences").in_file(filename));
+    }
+    let mut queries = Vec::new();
+    for record in fasta {
+        if record.seq.is_empty() {
+            return Err(
+                Error::validation(&record.header, "empty sequence").at(filename, record.line)
+            );
+        }
+        queries.push((record.header, record.seq));
+    }
+    Ok(queries)
+}
+
+fn read_records<R: Read>(filename: &Path, reader: R) -> Result<Vec<FastaRecord>> {
+    let buf = BufReader::new(reader);
+    let mut fasta: Vec<FastaRecord> = Vec::new();
+    for (n, line) in buf.lines().enumerate() {
+        let line = line.map_err(|err| Error::io(filename, err))?;
+        if line.starts_with('>') {
+            fasta.push(FastaRecord {
+                line: n + 1,
+                header: line.trim().to_string(),
+                seq: String::new(),
+            });
+        } else {
+            match fasta.last_mut() {
+                Some(record) => record.seq.push_str(line.trim()),
+                None if line.trim().is_empty() => {}
+                None => {
+                    return Err(
+                        Error::parse(&line, "sequence before the first header").at(filename, n + 1)
+                    )
+                }
+            }
+        }
+    }
+    Ok(fasta)
+}
@@ -0,0 +1,438 @@
+//! FM-index over each chromosome of a genome so repeated searches against the
+//! same reference do not have to rescan every window. The index is built once
+//! from the decoded genome and written next to it as `<genome>.fmi`, with the
+//! size and modification time of the genome so a changed genome is indexed
+//! again.
+use crate::error::{Error, Result};
+use crate::fasta::Genome;
+use crate::header::chromosome_name;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"KBFMI002";
+// $, a, c, g, t and everything else (n, iupac codes, ...)
+const SIGMA: usize = 6;
+const OCC_STEP: usize = 64;
+const SA_STEP: usize = 32;
+
+/// FM-index of one chromosome.
+pub struct ChromIndex {
+    /// Header line of the chromosome in the genome FASTA.
+    pub header: String,
+    pub len: usize,
+    bwt: Vec<u8>,
+    c: [usize; SIGMA + 1],
+    occ: Vec<[u32; SIGMA]>,
+    sa_samples: Vec<u32>,
+    marked: Vec<u64>,
+    marked_rank: Vec<u32>,
+}
+
+/// FM-indexes of every chromosome of a genome.
+pub struct GenomeIndex {
+    pub chroms: Vec<ChromIndex>,
+    /// The genome FASTA the index was built from.
+    pub source: SourceStamp,
+}
+
+/// How [`GenomeIndex::load_or_build`] came by its index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexOrigin {
+    Loaded,
+    /// Built and saved, there was no index yet.
+    Built,
+    /// Built and saved again, the genome changed since the index was saved.
+    Rebuilt,
+}
+
+/// Size and modification time of a genome FASTA, all 0 when not known.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SourceStamp {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch.
+    pub modified: u64,
+}
+
+impl SourceStamp {
+    pub fn of(path: &Path) -> Result<SourceStamp> {
+        let meta = fs::metadata(path).map_err(|err| Error::io(path, err))?;
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(SourceStamp {
+            size: meta.len(),
+            modified,
+        })
+    }
+}
+
+fn encode(nuc: u8) -> u8 {
+    match nuc.to_ascii_lowercase() {
+        b'a' => 1,
+        b'c' => 2,
+        b'g' => 3,
+        b't' => 4,
+        _ => 5,
+    }
+}
+
+fn suffix_array(text: &[u8]) -> Vec<u32> {
+    // prefix doubling, the text must end in a unique smallest symbol
+    let n = text.len();
+    let mut sa: Vec<u32> = (0..n as u32).collect();
+    let mut rank: Vec<u32> = text.iter().map(|&c| c as u32).collect();
+    let mut tmp: Vec<u32> = vec![0; n];
+    let mut k = 1;
+    loop {
+        let key = |i: u32| {
+            let i = i as usize;
+            let second = if i + k < n { rank[i + k] + 1 } else { 0 };
+            (rank[i], second)
+        };
+        sa.sort_unstable_by_key(|&i| key(i));
+        tmp[sa[0] as usize] = 0;
+        for j in 1..n {
+            let step = (key(sa[j - 1]) < key(sa[j])) as u32;
+            tmp[sa[j] as usize] = tmp[sa[j - 1] as usize] + step;
+        }
+        std::mem::swap(&mut rank, &mut tmp);
+        if rank[sa[n - 1] as usize] as usize == n - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+impl ChromIndex {
+    /// Builds the index of one chromosome sequence.
+    pub fn build(header: String, seq: &str) -> ChromIndex {
+        let mut text: Vec<u8> = seq.bytes().map(encode).collect();
+        text.push(0);
+        let sa = suffix_array(&text);
+        let n = text.len();
+
+        let mut bwt: Vec<u8> = Vec::with_capacity(n);
+        let mut marked: Vec<u64> = vec![0; n / 64 + 1];
+        let mut sa_samples: Vec<u32> = Vec::new();
+        for (row, &pos) in sa.iter().enumerate() {
+            let pos = pos as usize;
+            bwt.push(if pos == 0 { text[n - 1] } else { text[pos - 1] });
+            if pos.is_multiple_of(SA_STEP) {
+                marked[row / 64] |= 1 << (row % 64);
+                sa_samples.push(pos as u32);
+            }
+        }
+        ChromIndex::from_parts(header, bwt, sa_samples, marked)
+    }
+
+    fn from_parts(
+        header: String,
+        bwt: Vec<u8>,
+        sa_samples: Vec<u32>,
+        marked: Vec<u64>,
+    ) -> ChromIndex {
+        let mut counts = [0u32; SIGMA];
+        let mut occ: Vec<[u32; SIGMA]> = Vec::with_capacity(bwt.len() / OCC_STEP + 1);
+        for (i, &b) in bwt.iter().enumerate() {
+            if i.is_multiple_of(OCC_STEP) {
+                occ.push(counts);
+            }
+            counts[b as usize] += 1;
+        }
+        occ.push(counts);
+
+        let mut c = [0usize; SIGMA + 1];
+        for x in 0..SIGMA {
+            c[x + 1] = c[x] + counts[x] as usize;
+        }
+
+        let mut marked_rank: Vec<u32> = Vec::with_capacity(marked.len());
+        let mut total = 0;
+        for word in &marked {
+            marked_rank.push(total);
+            total += word.count_ones();
+        }
+
+        ChromIndex {
+            header,
+            len: bwt.len() - 1,
+            bwt,
+            c,
+            occ,
+            sa_samples,
+            marked,
+            marked_rank,
+        }
+    }
+
+    // number of `sym` in bwt[..i]
+    fn occ(&self, sym: u8, i: usize) -> usize {
+        let block = i / OCC_STEP;
+        let mut count = self.occ[block][sym as usize] as usize;
+        for &b in &self.bwt[block * OCC_STEP..i] {
+            if b == sym {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn lf(&self, row: usize) -> usize {
+        let sym = self.bwt[row];
+        self.c[sym as usize] + self.occ(sym, row)
+    }
+
+    fn is_marked(&self, row: usize) -> bool {
+        self.marked[row / 64] >> (row % 64) & 1 == 1
+    }
+
+    fn sample_at(&self, row: usize) -> usize {
+        let mask = (1u64 << (row % 64)) - 1;
+        let rank = self.marked_rank[row / 64] + (self.marked[row / 64] & mask).count_ones();
+        self.sa_samples[rank as usize] as usize
+    }
+
+    // backward search, returns the half open range of suffix array rows
+    fn range(&self, pattern: &[u8]) -> (usize, usize) {
+        let mut l = 0;
+        let mut r = self.bwt.len();
+        for &nuc in pattern.iter().rev() {
+            let sym = encode(nuc);
+            l = self.c[sym as usize] + self.occ(sym, l);
+            r = self.c[sym as usize] + self.occ(sym, r);
+            if l >= r {
+                return (0, 0);
+            }
+        }
+        (l, r)
+    }
+
+    /// Chromosome name of the header, see [`chromosome_name`].
+    pub fn name(&self) -> String {
+        chromosome_name(&self.header)
+    }
+
+    /// 0 based start of every exact occurrence of `pattern`, sorted.
+    pub fn locate(&self, pattern: &[u8]) -> Vec<usize> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        let (l, r) = self.range(pattern);
+        let mut hits: Vec<usize> = Vec::with_capacity(r - l);
+        for mut row in l..r {
+            let mut steps = 0;
+            while !self.is_marked(row) {
+                row = self.lf(row);
+                steps += 1;
+            }
+            hits.push(self.sample_at(row) + steps);
+        }
+        hits.sort_unstable();
+        hits
+    }
+
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_bytes(out, self.header.as_bytes())?;
+        write_bytes(out, &self.bwt)?;
+        write_u64(out, self.sa_samples.len() as u64)?;
+        for s in &self.sa_samples {
+            out.write_all(&s.to_le_bytes())?;
+        }
+        write_u64(out, self.marked.len() as u64)?;
+        for w in &self.marked {
+            write_u64(out, *w)?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(inp: &mut R) -> io::Result<ChromIndex> {
+        let header = String::from_utf8(read_bytes(inp)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let bwt = read_bytes(inp)?;
+        let n_samples = read_u64(inp)? as usize;
+        let mut sa_samples: Vec<u32> = Vec::with_capacity(n_samples);
+        let mut buf = [0u8; 4];
+        for _ in 0..n_samples {
+            inp.read_exact(&mut buf)?;
+            sa_samples.push(u32::from_le_bytes(buf));
+        }
+        let n_marked = read_u64(inp)? as usize;
+        let mut marked: Vec<u64> = Vec::with_capacity(n_marked);
+        for _ in 0..n_marked {
+            marked.push(read_u64(inp)?);
+        }
+        Ok(ChromIndex::from_parts(header, bwt, sa_samples, marked))
+    }
+}
+
+impl GenomeIndex {
+    pub fn build(genome: &Genome) -> GenomeIndex {
+        let mut chroms: Vec<ChromIndex> = Vec::new();
+        for chrom in &genome.chromosomes {
+            chroms.push(ChromIndex::build(chrom.header.clone(), &chrom.seq));
+        }
+        GenomeIndex {
+            chroms,
+            source: SourceStamp::default(),
+        }
+    }
+
+    /// Builds the index of the genome FASTA `full_geno`.
+    pub fn build_for(full_geno: &Path) -> Result<GenomeIndex> {
+        let source = SourceStamp::of(full_geno)?;
+        let mut index = GenomeIndex::build(&Genome::read(full_geno)?);
+        index.source = source;
+        Ok(index)
+    }
+
+    /// Path of the index belonging to a genome FASTA.
+    pub fn path_for(full_geno: &Path) -> PathBuf {
+        let mut index_path = full_geno.as_os_str().to_owned();
+        index_path.push(".fmi");
+        PathBuf::from(index_path)
+    }
+
+    /// Loads the index next to `full_geno`, building and saving it first if
+    /// it does not exist yet or was built from a different genome FASTA.
+    pub fn load_or_build(full_geno: &Path) -> Result<(GenomeIndex, IndexOrigin)> {
+        let index_path = GenomeIndex::path_for(full_geno);
+        let origin = match index_path.exists() {
+            true if GenomeIndex::stamp(&index_path)? == Some(SourceStamp::of(full_geno)?) => {
+                return Ok((GenomeIndex::load(index_path)?, IndexOrigin::Loaded));
+            }
+            true => IndexOrigin::Rebuilt,
+            false => IndexOrigin::Built,
+        };
+        let index = GenomeIndex::build_for(full_geno)?;
+        index.save(index_path)?;
+        Ok((index, origin))
+    }
+
+    /// The genome an index file was built from, `None` for an index of an
+    /// older kbrecondo.
+    pub fn stamp(filename: &Path) -> Result<Option<SourceStamp>> {
+        let read = || -> io::Result<Option<SourceStamp>> {
+            let mut inp = BufReader::new(File::open(filename)?);
+            let mut magic = [0u8; 8];
+            if inp.read_exact(&mut magic).is_err() || &magic != MAGIC {
+                return Ok(None);
+            }
+            Ok(Some(SourceStamp {
+                size: read_u64(&mut inp)?,
+                modified: read_u64(&mut inp)?,
+            }))
+        };
+        read().map_err(|err| Error::io(filename, err))
+    }
+
+    pub fn save<P>(&self, filename: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = filename.as_ref();
+        let write = || -> io::Result<()> {
+            let mut out = BufWriter::new(File::create(filename)?);
+            out.write_all(MAGIC)?;
+            write_u64(&mut out, self.source.size)?;
+            write_u64(&mut out, self.source.modified)?;
+            write_u64(&mut out, self.chroms.len() as u64)?;
+            for chrom in &self.chroms {
+                chrom.write(&mut out)?;
+            }
+            out.flush()
+        };
+        write().map_err(|err| Error::io(filename, err))
+    }
+
+    pub fn load<P>(filename: P) -> Result<GenomeIndex>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = filename.as_ref();
+        let file = File::open(filename).map_err(|err| Error::io(filename, err))?;
+        let mut inp = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        inp.read_exact(&mut magic)
+            .map_err(|err| Error::io(filename, err))?;
+        if &magic != MAGIC {
+            return Err(Error::parse(
+                "index",
+                "not a kbrecondo index file, or one of an older version, rerun kbrecondo index",
+            )
+            .in_file(filename));
+        }
+        let read = |inp: &mut BufReader<File>| -> io::Result<GenomeIndex> {
+            let source = SourceStamp {
+                size: read_u64(inp)?,
+                modified: read_u64(inp)?,
+            };
+            let n = read_u64(inp)? as usize;
+            let mut chroms: Vec<ChromIndex> = Vec::with_capacity(n);
+            for _ in 0..n {
+                chroms.push(ChromIndex::read(inp)?);
+            }
+            Ok(GenomeIndex { chroms, source })
+        };
+        read(&mut inp).map_err(|err| Error::io(filename, err))
+    }
+}
+
+fn write_u64<W: Write>(out: &mut W, val: u64) -> io::Result<()> {
+    out.write_all(&val.to_le_bytes())
+}
+
+fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u64(out, bytes.len() as u64)?;
+    out.write_all(bytes)
+}
+
+fn read_u64<R: Read>(inp: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    inp.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes<R: Read>(inp: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u64(inp)? as usize;
+    let mut buf = vec![0u8; len];
+    inp.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_of_a_changed_genome_is_built_again() {
+        let dir = std::env::temp_dir().join(format!("kbrecondo_fmi_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let genome = dir.join("genome.fna");
+        fs::write(&genome, ">chr1\nACGTACGTAA\n").unwrap();
+
+        let (index, origin) = GenomeIndex::load_or_build(&genome).unwrap();
+        assert_eq!(origin, IndexOrigin::Built);
+        assert_eq!(index.chroms[0].locate(b"acgt"), vec![0, 4]);
+        let index_path = GenomeIndex::path_for(&genome);
+        assert_eq!(
+            GenomeIndex::stamp(&index_path).unwrap(),
+            Some(SourceStamp::of(&genome).unwrap())
+        );
+        let (index, origin) = GenomeIndex::load_or_build(&genome).unwrap();
+        assert_eq!(origin, IndexOrigin::Loaded);
+        assert_eq!(index.chroms[0].len, 10);
+
+        // a different size is noticed whatever the clock says
+        fs::write(&genome, ">chr1\nTTACGTTTTTTT\n").unwrap();
+        let (index, origin) = GenomeIndex::load_or_build(&genome).unwrap();
+        assert_eq!(origin, IndexOrigin::Rebuilt);
+        assert_eq!(index.chroms[0].locate(b"acgt"), vec![2]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
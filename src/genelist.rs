@@ -0,0 +1,502 @@
+//! Gene lists used to restrict a search to a set of genes.
+//!
+//! A list is a csv or tsv with a header row, or plain text with one gene per
+//! line and no header (`#` starts a comment). The format is taken from the
+//! extension (`.csv`, `.tsv`, `.tab`, `.txt`) or else from the first line.
+//!
+//! The other columns of a csv or tsv are labels of the genes (e.g. the gene
+//! set they belong to) that are carried through to the output.
+//!
+//! A [`GeneMatcher`] then picks the annotation records the list names.
+use crate::error::{Error, Result};
+use crate::header::{AnnotationRecord, Field};
+use csv::ReaderBuilder;
+use regex::RegexSet;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{stdin, Read};
+use std::path::Path;
+
+/// Column holding the gene ids.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// 1 based
+    Index(usize),
+    /// Name in the header row.
+    Name(String),
+}
+
+impl Column {
+    /// A number is a 1 based index, anything else a header name.
+    pub fn parse(column: &str) -> Result<Column> {
+        match column.parse::<usize>() {
+            Ok(0) => Err(Error::config("gene list columns are counted from 1")),
+            Ok(index) => Ok(Column::Index(index)),
+            Err(_) => Ok(Column::Name(column.to_string())),
+        }
+    }
+}
+
+impl Default for Column {
+    fn default() -> Column {
+        Column::Index(1)
+    }
+}
+
+/// One gene of a list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneEntry {
+    pub id: String,
+    /// Line of the entry in the list, 1 based.
+    pub line: usize,
+    /// Values of the label columns, empty where a row is short.
+    pub labels: Vec<String>,
+}
+
+/// A read gene list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeneList {
+    /// Header names of the label columns.
+    pub label_names: Vec<String>,
+    pub entries: Vec<GeneEntry>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Delimited(u8),
+    Plain,
+}
+
+fn sniff(filename: &Path, text: &str) -> Format {
+    let extension = filename
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => Format::Delimited(b','),
+        Some("tsv") | Some("tab") => Format::Delimited(b'\t'),
+        Some("txt") => Format::Plain,
+        _ => {
+            let first = text.lines().find(|line| !line.trim().is_empty());
+            match first {
+                Some(line) if line.contains('\t') => Format::Delimited(b'\t'),
+                Some(line) if line.contains(',') => Format::Delimited(b','),
+                _ => Format::Plain,
+            }
+        }
+    }
+}
+
+/// Reads the genes of `column` from a gene list file, or from stdin when
+/// the path is `-`. Empty ids are skipped. `labels` are the label columns,
+/// every other column if empty.
+pub fn read_gene_list<P>(filename: P, column: &Column, labels: &[Column]) -> Result<GeneList>
+where
+    P: AsRef<Path>,
+{
+    let filename = filename.as_ref();
+    let mut text = String::new();
+    if filename == Path::new("-") {
+        stdin()
+            .read_to_string(&mut text)
+            .map_err(|err| Error::io(filename, err))?;
+    } else {
+        text = fs::read_to_string(filename).map_err(|err| Error::io(filename, err))?;
+    }
+
+    let mut list = GeneList::default();
+    match sniff(filename, &text) {
+        Format::Plain => {
+            for column in std::iter::once(column).chain(labels) {
+                if *column != Column::Index(1) {
+                    return Err(Error::config(format!(
+                        "{} has one gene per line, there is no column {:?}",
+                        filename.display(),
+                        column
+                    )));
+                }
+            }
+            for (n, line) in text.lines().enumerate() {
+                let id = line.split('#').next().unwrap_or("").trim();
+                if !id.is_empty() {
+                    list.entries.push(GeneEntry {
+                        id: id.to_string(),
+                        line: n + 1,
+                        labels: Vec::new(),
+                    });
+                }
+            }
+        }
+        Format::Delimited(delimiter) => {
+            let mut rdr = ReaderBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .from_reader(text.as_bytes());
+            let headers = rdr
+                .headers()
+                .map_err(|err| csv_error(filename, err))?
+                .clone();
+            let find = |column: &Column| match column {
+                Column::Index(index) => Ok(index - 1),
+                Column::Name(name) => headers.iter().position(|h| h == name).ok_or_else(|| {
+                    Error::config(format!(
+                        "{} has no column {:?}, its columns are {}",
+                        filename.display(),
+                        name,
+                        headers.iter().collect::<Vec<_>>().join(", ")
+                    ))
+                }),
+            };
+            let index = find(column)?;
+            let label_indices: Vec<usize> = if labels.is_empty() {
+                (0..headers.len()).filter(|&i| i != index).collect()
+            } else {
+                labels.iter().map(find).collect::<Result<_>>()?
+            };
+            list.label_names = label_indices
+                .iter()
+                .map(|&i| match headers.get(i) {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => format!("label.{}", i + 1),
+                })
+                .collect();
+            for record in rdr.records() {
+                let rec = record.map_err(|err| csv_error(filename, err))?;
+                let line = rec.position().map(|pos| pos.line() as usize).unwrap_or(0);
+                let Some(id) = rec.get(index) else {
+                    return Err(Error::parse(
+                        &rec.iter().collect::<Vec<_>>().join(","),
+                        format!("no column {}", index + 1),
+                    )
+                    .at(filename, line));
+                };
+                if !id.is_empty() {
+                    list.entries.push(GeneEntry {
+                        id: id.to_string(),
+                        line,
+                        labels: label_indices
+                            .iter()
+                            .map(|&i| rec.get(i).unwrap_or("").to_string())
+                            .collect(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(list)
+}
+
+fn csv_error(filename: &Path, err: csv::Error) -> Error {
+    let line = err.position().map(|pos| pos.line() as usize);
+    let message = err.to_string();
+    match (err.into_kind(), line) {
+        (csv::ErrorKind::Io(source), _) => Error::io(filename, source),
+        (_, Some(line)) => Error::parse("csv", message).at(filename, line),
+        (_, None) => Error::parse("csv", message).in_file(filename),
+    }
+}
+
+/// How gene list entries are compared with a field of the annotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The field equals the entry.
+    Exact,
+    /// The field starts with the entry, e.g. a gene and all its transcripts.
+    Prefix,
+    /// The entry is a regex found in the field, `^...$` to match all of it.
+    Regex,
+    /// The field contains the part of the entry after its last `_`, as the
+    /// old `-m` mode did.
+    Contains,
+}
+
+impl MatchMode {
+    /// `exact`, `prefix`, `regex` or `contains`.
+    pub fn parse(mode: &str) -> Result<MatchMode> {
+        match mode {
+            "exact" => Ok(MatchMode::Exact),
+            "prefix" => Ok(MatchMode::Prefix),
+            "regex" => Ok(MatchMode::Regex),
+            "contains" => Ok(MatchMode::Contains),
+            _ => Err(Error::config(format!(
+                "unknown match mode {:?}, expected exact, prefix, regex or contains",
+                mode
+            ))),
+        }
+    }
+}
+
+/// Annotation records picked by a gene list.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    /// Each record once, in annotation order.
+    pub records: Vec<AnnotationRecord>,
+    /// Per record, the list entries that picked it.
+    pub entries: Vec<Vec<usize>>,
+    /// List entries that picked no record.
+    pub unmatched: Vec<usize>,
+}
+
+/// Matches a gene list against a field of the annotation records.
+pub struct GeneMatcher {
+    pub label_names: Vec<String>,
+    pub entries: Vec<GeneEntry>,
+    pub mode: MatchMode,
+    pub field: Field,
+    exact: HashMap<String, Vec<usize>>,
+    regexes: Option<RegexSet>,
+}
+
+impl GeneMatcher {
+    pub fn new(list: GeneList, mode: MatchMode, field: Field) -> Result<GeneMatcher> {
+        let GeneList {
+            label_names,
+            entries,
+        } = list;
+        let mut exact: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut regexes = None;
+        match mode {
+            MatchMode::Exact => {
+                for (n, entry) in entries.iter().enumerate() {
+                    exact.entry(entry.id.clone()).or_default().push(n);
+                }
+            }
+            MatchMode::Regex => {
+                for entry in &entries {
+                    if let Err(err) = regex::Regex::new(&entry.id) {
+                        return Err(Error::parse(&entry.id, format!("bad regex: {}", err))
+                            .at_line(entry.line));
+                    }
+                }
+                let set = RegexSet::new(entries.iter().map(|entry| &entry.id))
+                    .map_err(|err| Error::config(format!("bad gene list regex: {}", err)))?;
+                regexes = Some(set);
+            }
+            MatchMode::Prefix | MatchMode::Contains => {}
+        }
+        Ok(GeneMatcher {
+            label_names,
+            entries,
+            mode,
+            field,
+            exact,
+            regexes,
+        })
+    }
+
+    /// The list entries matching `record`.
+    pub fn matches(&self, record: &AnnotationRecord) -> Vec<usize> {
+        let Some(value) = record.field(self.field) else {
+            return Vec::new();
+        };
+        let value = value.trim();
+        match self.mode {
+            MatchMode::Exact => self.exact.get(value).cloned().unwrap_or_default(),
+            MatchMode::Regex => match &self.regexes {
+                Some(set) => set.matches(value).into_iter().collect(),
+                None => Vec::new(),
+            },
+            MatchMode::Prefix => self.find(|id| value.starts_with(id)),
+            MatchMode::Contains => self.find(|id| {
+                let part = id.split('_').next_back().unwrap_or(id);
+                !part.is_empty() && value.contains(part)
+            }),
+        }
+    }
+
+    /// Labels of `record`, one per label column. A record picked by several
+    /// entries gets their distinct values joined by `;`.
+    pub fn labels(&self, record: &AnnotationRecord) -> Vec<String> {
+        let found = self.matches(record);
+        (0..self.label_names.len())
+            .map(|column| {
+                let mut values: Vec<&str> = Vec::new();
+                for &n in &found {
+                    let value = self.entries[n].labels[column].as_str();
+                    if !value.is_empty() && !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                values.join(";")
+            })
+            .collect()
+    }
+
+    fn find<F: Fn(&str) -> bool>(&self, test: F) -> Vec<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| test(&entry.id))
+            .map(|(n, _)| n)
+            .collect()
+    }
+
+    /// The records matched by at least one entry, and the entries that
+    /// matched nothing.
+    pub fn select(&self, annotation: &[AnnotationRecord]) -> Selection {
+        let mut selection = Selection::default();
+        let mut used = vec![false; self.entries.len()];
+        for record in annotation {
+            let found = self.matches(record);
+            if found.is_empty() {
+                continue;
+            }
+            for &n in &found {
+                used[n] = true;
+            }
+            selection.records.push(record.clone());
+            selection.entries.push(found);
+        }
+        selection.unmatched = (0..self.entries.len()).filter(|&n| !used[n]).collect();
+        selection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Strand;
+
+    fn list(ids: &[(&str, &str)]) -> GeneList {
+        GeneList {
+            label_names: vec![String::from("set")],
+            entries: ids
+                .iter()
+                .enumerate()
+                .map(|(n, (id, label))| GeneEntry {
+                    id: id.to_string(),
+                    line: n + 2,
+                    labels: vec![label.to_string()],
+                })
+                .collect(),
+        }
+    }
+
+    fn gene(name: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            id: format!("medtr.A17.gnm5.ann1_6.{}.1", name),
+            gene_name: Some(name.to_string()),
+            chromosome: String::from("MtrunA17Chr1"),
+            begin: 1,
+            end: 10,
+            strand: Strand::Plus,
+            length: None,
+            locus: None,
+            definition: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    fn names(matcher: &GeneMatcher, annotation: &[AnnotationRecord]) -> Vec<String> {
+        matcher
+            .select(annotation)
+            .records
+            .into_iter()
+            .filter_map(|record| record.gene_name)
+            .collect()
+    }
+
+    #[test]
+    fn exact_prefix_and_contains() {
+        let annotation = [gene("MtrunA17Chr1g0147"), gene("MtrunA17Chr1g01471")];
+        let entries = list(&[("MtrunA17Chr1g0147", "a")]);
+        let exact = GeneMatcher::new(entries.clone(), MatchMode::Exact, Field::GeneName).unwrap();
+        // the gene, not the one its name is the start of
+        assert_eq!(names(&exact, &annotation), vec!["MtrunA17Chr1g0147"]);
+        let prefix = GeneMatcher::new(entries, MatchMode::Prefix, Field::GeneName).unwrap();
+        assert_eq!(names(&prefix, &annotation).len(), 2);
+
+        // contains uses the part after the last _, as the old -m mode did
+        let entries = list(&[("x_g01471", "a")]);
+        let contains = GeneMatcher::new(entries, MatchMode::Contains, Field::GeneName).unwrap();
+        assert_eq!(names(&contains, &annotation), vec!["MtrunA17Chr1g01471"]);
+        let regex = GeneMatcher::new(
+            list(&[("^MtrunA17Chr1g0147$", "a")]),
+            MatchMode::Regex,
+            Field::GeneName,
+        )
+        .unwrap();
+        assert_eq!(names(&regex, &annotation), vec!["MtrunA17Chr1g0147"]);
+    }
+
+    #[test]
+    fn duplicate_entries_select_a_gene_once() {
+        let annotation = [gene("MtrunA17Chr1g0147"), gene("MtrunA17Chr2g0200")];
+        let entries = list(&[
+            ("MtrunA17Chr1g0147", "a"),
+            ("MtrunA17Chr1g0147", "a"),
+            ("MtrunA17Chr1g0147", "b"),
+            ("MtrunA17Chr9g0999", "c"),
+        ]);
+        for mode in [MatchMode::Exact, MatchMode::Prefix] {
+            let matcher = GeneMatcher::new(entries.clone(), mode, Field::GeneName).unwrap();
+            let selection = matcher.select(&annotation);
+            assert_eq!(selection.records.len(), 1);
+            assert_eq!(selection.entries, vec![vec![0, 1, 2]]);
+            assert_eq!(selection.unmatched, vec![3]);
+            assert_eq!(matcher.labels(&annotation[0]), vec!["a;b"]);
+        }
+    }
+
+    fn read(name: &str, text: &str, column: &Column, labels: &[Column]) -> Result<GeneList> {
+        let path = std::env::temp_dir().join(format!("kbrecondo_{}_{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let list = read_gene_list(&path, column, labels);
+        fs::remove_file(&path).unwrap();
+        list
+    }
+
+    fn ids(list: &GeneList) -> Vec<&str> {
+        list.entries.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn sniffs_the_extension_then_the_first_line() {
+        let tsv = "gene\tset\nNIN\tearly\n";
+        assert_eq!(sniff(Path::new("genes.csv"), tsv), Format::Delimited(b','));
+        assert_eq!(sniff(Path::new("genes.TAB"), ""), Format::Delimited(b'\t'));
+        assert_eq!(sniff(Path::new("genes.txt"), tsv), Format::Plain);
+        assert_eq!(sniff(Path::new("genes"), tsv), Format::Delimited(b'\t'));
+        assert_eq!(
+            sniff(Path::new("genes.list"), "\ngene,set\n"),
+            Format::Delimited(b',')
+        );
+        assert_eq!(sniff(Path::new("genes"), "NIN\nNSP1\n"), Format::Plain);
+    }
+
+    #[test]
+    fn reads_a_column_by_name_with_short_rows() {
+        let text = "set,gene,note\nearly,NIN,nodule\nearly,NSP1\nlate,,x\n";
+        let gene = Column::Name(String::from("gene"));
+        let list = read("named.csv", text, &gene, &[]).unwrap();
+        assert_eq!(ids(&list), ["NIN", "NSP1"]);
+        assert_eq!(list.label_names, ["set", "note"]);
+        assert_eq!(list.entries[0].labels, ["early", "nodule"]);
+        assert_eq!(list.entries[0].line, 2);
+        // the note is missing from the short row
+        assert_eq!(list.entries[1].labels, ["early", ""]);
+
+        let list = read("labels.csv", text, &gene, &[Column::Index(3)]).unwrap();
+        assert_eq!(list.label_names, ["note"]);
+
+        let missing = Column::Name(String::from("locus"));
+        let err = read("missing.csv", text, &missing, &[]).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+        assert!(err.to_string().contains("set, gene, note"), "{}", err);
+    }
+
+    #[test]
+    fn reads_plain_text_with_comments() {
+        let text = "# nodulation genes\nNIN\n\nNSP1  # GRAS\n";
+        let list = read("plain.txt", text, &Column::default(), &[]).unwrap();
+        assert_eq!(ids(&list), ["NIN", "NSP1"]);
+        assert_eq!(list.entries[1].line, 4);
+        assert!(list.label_names.is_empty());
+
+        let err = read("column.txt", text, &Column::Index(2), &[]).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+        let name = Column::Name(String::from("set"));
+        let err = read("label.txt", text, &Column::default(), &[name]).unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+    }
+}
@@ -0,0 +1,251 @@
+//! Parsing of legumeinfo style FASTA headers, e.g.
+//! `>medtr.A17.gnm5.ann1_6.MtrunA17Chr1g0146001.1 gn=MtrunA17Chr1g0146001
+//! chr=MtrunA17Chr1 begin=24712 end=25881 strand=+ len=1170 def=...`.
+use crate::error::{Error, Result};
+use std::fmt;
+
+/// Strand of an annotated gene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strand {
+    Plus,
+    Minus,
+}
+
+impl Strand {
+    pub fn parse(strand: &str) -> Result<Strand> {
+        match strand {
+            "+" => Ok(Strand::Plus),
+            "-" => Ok(Strand::Minus),
+            _ => Err(Error::parse(strand, "strand= must be + or -")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strand::Plus => "+",
+            Strand::Minus => "-",
+        }
+    }
+}
+
+impl fmt::Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A field of an [`AnnotationRecord`] genes can be named by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    GeneName,
+    Locus,
+}
+
+impl Field {
+    /// `id`, `gn` or `locus`.
+    pub fn parse(field: &str) -> Result<Field> {
+        match field {
+            "id" => Ok(Field::Id),
+            "gn" | "gene" => Ok(Field::GeneName),
+            "locus" | "loc" => Ok(Field::Locus),
+            _ => Err(Error::config(format!(
+                "unknown field {:?}, expected id, gn or locus",
+                field
+            ))),
+        }
+    }
+}
+
+/// An annotated gene (or cds, mrna, ...) from the annotation FASTA.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnotationRecord {
+    /// First word of the header, without the leading `>`.
+    pub id: String,
+    /// `gn=`
+    pub gene_name: Option<String>,
+    /// `chr=`, matched against the `acc=` of the genome FASTA headers.
+    pub chromosome: String,
+    /// `begin=`, 1 based
+    pub begin: usize,
+    /// `end=`, 1 based and inclusive
+    pub end: usize,
+    /// `strand=`
+    pub strand: Strand,
+    /// `len=`
+    pub length: Option<usize>,
+    /// `loc=`
+    pub locus: Option<String>,
+    /// `def=`, free text
+    pub definition: Option<String>,
+    /// Every other `key=value` field, in header order.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl AnnotationRecord {
+    /// Parses an annotation header line. `chr=`, `begin=`, `end=` and
+    /// `strand=` are required.
+    pub fn from_header(header: &str) -> Result<AnnotationRecord> {
+        let (id, fields) = parse_header(header);
+        let mut gene_name = None;
+        let mut chromosome = None;
+        let mut begin = None;
+        let mut end = None;
+        let mut strand = None;
+        let mut length = None;
+        let mut locus = None;
+        let mut definition = None;
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        for (key, value) in fields {
+            match key.as_str() {
+                "gn" => gene_name = Some(value),
+                "chr" => chromosome = Some(value),
+                "begin" => begin = Some(parse_number(&id, &key, &value)?),
+                "end" => end = Some(parse_number(&id, &key, &value)?),
+                "strand" => {
+                    strand = Some(
+                        Strand::parse(&value)
+                            .map_err(|_| Error::parse(&id, format!("bad strand={}", value)))?,
+                    )
+                }
+                "len" => length = Some(parse_number(&id, &key, &value)?),
+                "loc" => locus = Some(value),
+                "def" => definition = Some(value),
+                _ => attributes.push((key, value)),
+            }
+        }
+        let missing = |key: &str| Error::parse(&id, format!("missing {}=", key));
+        let record = AnnotationRecord {
+            chromosome: chromosome.ok_or_else(|| missing("chr"))?,
+            begin: begin.ok_or_else(|| missing("begin"))?,
+            end: end.ok_or_else(|| missing("end"))?,
+            strand: strand.ok_or_else(|| missing("strand"))?,
+            id,
+            gene_name,
+            length,
+            locus,
+            definition,
+            attributes,
+        };
+        record.check()
+    }
+
+    /// Checks that `begin` is at least 1 and before `end`.
+    pub fn check(self) -> Result<AnnotationRecord> {
+        if self.begin == 0 {
+            return Err(Error::validation(
+                &self.id,
+                "begin=0, coordinates are 1 based",
+            ));
+        }
+        if self.begin >= self.end {
+            return Err(Error::validation(
+                &self.id,
+                format!("begin={} is not before end={}", self.begin, self.end),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Value of `field`, `None` if the header does not have it.
+    pub fn field(&self, field: Field) -> Option<&str> {
+        match field {
+            Field::Id => Some(&self.id),
+            Field::GeneName => self.gene_name.as_deref(),
+            Field::Locus => self.locus.as_deref(),
+        }
+    }
+
+    /// The definition followed by the extra attributes as `key=value`, space
+    /// separated.
+    pub fn info(&self) -> String {
+        let mut info: Vec<String> = Vec::new();
+        if let Some(definition) = &self.definition {
+            info.push(format!("def={}", definition));
+        }
+        for (key, value) in &self.attributes {
+            if key.is_empty() {
+                info.push(value.clone());
+            } else {
+                info.push(format!("{}={}", key, value));
+            }
+        }
+        info.join(" ")
+    }
+}
+
+fn parse_number(id: &str, key: &str, value: &str) -> Result<usize> {
+    value
+        .parse::<usize>()
+        .map_err(|_| Error::parse(id, format!("{}={} is not a number", key, value)))
+}
+
+/// Splits a header line into its id (first word, without `>`) and its
+/// `key=value` fields. Words without `=` belong to the value of the field
+/// before them, so free text such as `def=` keeps its spaces.
+pub fn parse_header(header: &str) -> (String, Vec<(String, String)>) {
+    let mut words = header.split_whitespace();
+    let id = words
+        .next()
+        .unwrap_or("")
+        .trim_start_matches('>')
+        .to_string();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for word in words {
+        match word.split_once('=') {
+            Some((key, value)) if !key.is_empty() => {
+                fields.push((key.to_string(), value.to_string()));
+            }
+            _ => match fields.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+                None => fields.push((String::new(), word.to_string())),
+            },
+        }
+    }
+    (id, fields)
+}
+
+/// Name of a genome sequence: its `acc=` field, or else the first word of the
+/// header as in NCBI and Ensembl genomes.
+pub fn chromosome_name(header: &str) -> String {
+    get_element(header, "acc").unwrap_or_else(|| parse_header(header).0)
+}
+
+/// Value of the first `key=` field of a header line.
+pub fn get_element(header: &str, key: &str) -> Option<String> {
+    let (_, fields) = parse_header(header);
+    fields
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = ">medtr.A17.gnm5.ann1_6.MtrunA17Chr1g0146001.1 gn=MtrunA17Chr1g0146001 chr=MtrunA17Chr1 begin=24712 end=25881 strand=+ len=1170 def=some protein";
+
+    #[test]
+    fn header_fields_are_parsed() {
+        let record = AnnotationRecord::from_header(HEADER).unwrap();
+        assert_eq!(record.id, "medtr.A17.gnm5.ann1_6.MtrunA17Chr1g0146001.1");
+        assert_eq!(record.chromosome, "MtrunA17Chr1");
+        assert_eq!((record.begin, record.end), (24712, 25881));
+        assert_eq!(record.strand, Strand::Plus);
+        assert_eq!(record.definition.as_deref(), Some("some protein"));
+    }
+
+    #[test]
+    fn begin_zero_is_rejected() {
+        let header = HEADER.replace("begin=24712", "begin=0");
+        let err = AnnotationRecord::from_header(&header).unwrap_err();
+        assert!(matches!(err, Error::Validation { .. }));
+        assert!(err.to_string().contains("coordinates are 1 based"));
+        let header = HEADER.replace("end=25881", "end=24712");
+        assert!(AnnotationRecord::from_header(&header).is_err());
+    }
+}
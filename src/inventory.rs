@@ -0,0 +1,210 @@
+//! What genomes and annotations the data directories hold, found with the
+//! same templates as a search uses.
+use crate::error::Result;
+use crate::paths::{find_matches, Names, TemplateMatch};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Species, genotype and assembly of a genome, `-` where the template does
+/// not say.
+pub type GenomeKey = (String, String, String);
+
+/// One genome assembly.
+#[derive(Clone, Debug)]
+pub struct GenomeEntry {
+    pub key: GenomeKey,
+    pub paths: Vec<PathBuf>,
+}
+
+/// One annotation version of an assembly.
+#[derive(Clone, Debug)]
+pub struct AnnotationEntry {
+    pub key: GenomeKey,
+    pub annotation: String,
+    /// Sequence type (cds, mrna, gene, protein, ...) and its file.
+    pub seq_types: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    pub genomes: Vec<GenomeEntry>,
+    pub annotations: Vec<AnnotationEntry>,
+}
+
+fn value(found: &TemplateMatch, names: &Names, field: &str) -> String {
+    found
+        .field(field)
+        .or_else(|| names.get(field))
+        .unwrap_or("-")
+        .to_string()
+}
+
+fn genome_key(found: &TemplateMatch, names: &Names) -> GenomeKey {
+    (
+        value(found, names, "species"),
+        value(found, names, "genotype"),
+        value(found, names, "assembly"),
+    )
+}
+
+impl Inventory {
+    /// Lists every file matching the templates. Placeholders set in `names`
+    /// narrow the listing, the others are read from the file names.
+    pub fn scan(
+        genomes: &Path,
+        genome_template: &str,
+        annotations: &Path,
+        annotation_template: &str,
+        names: &Names,
+    ) -> Result<Inventory> {
+        let mut genome_map: BTreeMap<GenomeKey, Vec<PathBuf>> = BTreeMap::new();
+        for found in find_matches(genomes, genome_template, names)? {
+            genome_map
+                .entry(genome_key(&found, names))
+                .or_default()
+                .push(found.path);
+        }
+
+        let mut annotation_map: BTreeMap<(GenomeKey, String), BTreeMap<String, PathBuf>> =
+            BTreeMap::new();
+        for found in find_matches(annotations, annotation_template, names)? {
+            let key = (
+                genome_key(&found, names),
+                value(&found, names, "annotation"),
+            );
+            annotation_map
+                .entry(key)
+                .or_default()
+                .insert(value(&found, names, "seq_type"), found.path);
+        }
+
+        Ok(Inventory {
+            genomes: genome_map
+                .into_iter()
+                .map(|(key, paths)| GenomeEntry { key, paths })
+                .collect(),
+            annotations: annotation_map
+                .into_iter()
+                .map(|((key, annotation), seq_types)| AnnotationEntry {
+                    key,
+                    annotation,
+                    seq_types,
+                })
+                .collect(),
+        })
+    }
+
+    /// Annotations without a genome, genomes without an annotation and
+    /// assemblies with more than one genome file.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+        for genome in &self.genomes {
+            let (species, genotype, assembly) = &genome.key;
+            if genome.paths.len() > 1 {
+                problems.push(format!(
+                    "{} {} {} has {} genome files",
+                    species,
+                    genotype,
+                    assembly,
+                    genome.paths.len()
+                ));
+            }
+            if !self.annotations.iter().any(|ann| ann.key == genome.key) {
+                problems.push(format!(
+                    "{} {} {} has a genome but no annotation",
+                    species, genotype, assembly
+                ));
+            }
+        }
+        for ann in &self.annotations {
+            let (species, genotype, assembly) = &ann.key;
+            if !self.genomes.iter().any(|genome| genome.key == ann.key) {
+                problems.push(format!(
+                    "{} {} {} annotation {} has no genome",
+                    species, genotype, assembly, ann.annotation
+                ));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ANNOTATION_TEMPLATE, GENOME_TEMPLATE};
+    use std::fs;
+
+    #[test]
+    fn scan_groups_the_files_and_finds_the_problems() {
+        let dir = std::env::temp_dir().join(format!("kbrecondo_inventory_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in [
+            "genomes/A17.gnm5.FL4S/medtr.A17.gnm5.FL4S.genome_main.fna.gz",
+            "genomes/A17.gnm5.ZZ99/medtr.A17.gnm5.ZZ99.genome_main.fna.gz",
+            "genomes/R108.gnm1.ZZ11/medtr.R108.gnm1.ZZ11.genome_main.fna.gz",
+            "annotations/A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.cds.fna.gz",
+            "annotations/A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.protein.faa.gz",
+            "annotations/A17.gnm5.ann2.AB12/medtr.A17.gnm5.ann2.AB12.cds.fna.gz",
+            "annotations/HM340.gnm1.ann1.QQ22/medtr.HM340.gnm1.ann1.QQ22.cds.fna.gz",
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let names = Names {
+            species: Some(String::from("medtr")),
+            ..Names::default()
+        };
+        let inventory = Inventory::scan(
+            &dir.join("genomes"),
+            GENOME_TEMPLATE,
+            &dir.join("annotations"),
+            ANNOTATION_TEMPLATE,
+            &names,
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let key = |genotype: &str, assembly: &str| {
+            (
+                String::from("medtr"),
+                genotype.to_string(),
+                assembly.to_string(),
+            )
+        };
+        let genomes: Vec<(&GenomeKey, usize)> = inventory
+            .genomes
+            .iter()
+            .map(|genome| (&genome.key, genome.paths.len()))
+            .collect();
+        assert_eq!(
+            genomes,
+            [(&key("A17", "gnm5"), 2), (&key("R108", "gnm1"), 1)]
+        );
+        let annotations: Vec<(&GenomeKey, &str, Vec<&str>)> = inventory
+            .annotations
+            .iter()
+            .map(|ann| {
+                let seq_types = ann.seq_types.keys().map(|s| s.as_str()).collect();
+                (&ann.key, ann.annotation.as_str(), seq_types)
+            })
+            .collect();
+        assert_eq!(
+            annotations,
+            [
+                (&key("A17", "gnm5"), "ann1_6", vec!["cds", "protein"]),
+                (&key("A17", "gnm5"), "ann2", vec!["cds"]),
+                (&key("HM340", "gnm1"), "ann1", vec!["cds"]),
+            ]
+        );
+        assert_eq!(
+            inventory.problems(),
+            [
+                "medtr A17 gnm5 has 2 genome files",
+                "medtr R108 gnm1 has a genome but no annotation",
+                "medtr HM340 gnm1 annotation ann1 has no genome",
+            ]
+        );
+    }
+}
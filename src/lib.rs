@@ -0,0 +1,49 @@
+//! kbrecondo searches the region around annotated genes for a sequence
+//! pattern. The binary is a thin command line wrapper around this crate, the
+//! pieces below can be used on their own. They do not write to stdout or
+//! stderr, reporting progress and warnings is left to the caller.
+//!
+//! - [`config`] reads data directories, species and defaults from TOML files
+//! - [`paths`] finds the genome and annotation files of a genotype
+//! - [`inventory`] lists every genome and annotation the data directories hold
+//! - [`fasta`] reads genome and annotation FASTAs into a [`Genome`]
+//!   and a list of [`AnnotationRecord`]s
+//! - [`header`] parses legumeinfo style `key=value` FASTA headers
+//! - [`dialect`] reads NCBI, Ensembl, Phytozome and user defined headers into
+//!   the same [`AnnotationRecord`]
+//! - [`window`] builds the [`Window`] searched around each gene and sets out
+//!   the coordinate conventions of the outputs
+//! - [`search`] finds [`Hit`]s in those windows, by scanning or through an
+//!   [`fmindex::GenomeIndex`]
+//! - [`pangenome`] lines the hits of several genotypes up gene by gene, or
+//!   by the orthogroups read by [`orthology`]
+//! - [`output`] writes the hits as csv, BED, GFF3 or JSON Lines, and the
+//!   genotype tables as csv
+//! - [`extract`] writes the windows and the hits with their flanks as FASTA
+//! - [`enrichment`] compares the hits around a gene set with a background
+//!
+//! Failures are reported as [`Error`], which carries the offending file, line
+//! and record where there is one.
+pub mod config;
+pub mod dialect;
+pub mod enrichment;
+pub mod error;
+pub mod extract;
+pub mod fasta;
+pub mod fmindex;
+pub mod genelist;
+pub mod header;
+pub mod inventory;
+pub mod matcher;
+pub mod orthology;
+pub mod output;
+pub mod pangenome;
+pub mod paths;
+pub mod search;
+pub mod window;
+
+pub use error::{Error, Result};
+pub use fasta::{Chromosome, Genome};
+pub use header::AnnotationRecord;
+pub use search::{GeneHits, GeneSummary, Hit};
+pub use window::Window;
@@ -1,639 +1,1381 @@
-use csv::{Reader, Writer};
-use flate2::read::MultiGzDecoder;
-use std::collections::{hash_map::Keys, HashMap};
-use std::fs::{read_dir, File};
-use std::io::{stdin, BufRead, BufReader};
+use clap::{Args, Parser, Subcommand};
+use csv::Writer;
+use kbrecondo::config::Config;
+use kbrecondo::dialect::Dialect;
+use kbrecondo::enrichment::{self, Background, Correction, EnrichOptions, Test};
+use kbrecondo::extract::{write_hit_fasta, write_window_fasta, HitContext};
+use kbrecondo::fasta::{read_annotation, read_search_fasta, read_search_fasta_single};
+use kbrecondo::fmindex::{GenomeIndex, IndexOrigin};
+use kbrecondo::genelist::{read_gene_list, Column, GeneMatcher, MatchMode, Selection};
+use kbrecondo::header::Field;
+use kbrecondo::inventory::Inventory;
+use kbrecondo::orthology::Orthogroups;
+use kbrecondo::output::{
+    finish_csv, write_bed, write_csv_labelled, write_enrichment, write_gff3, write_jsonl,
+    write_matrix, write_presence, write_run_summary, write_summary, Output, OutputFormat, RunInput,
+    RunParameters, RunSummary, CONTEXT_HEADER, CSV_HEADER, GFF3_HEADER, SUMMARY_HEADER,
+};
+use kbrecondo::pangenome::{self, GeneKey, PangenomeSearch, Progress};
+use kbrecondo::paths::{find_one, Names, TemplateMatch};
+use kbrecondo::search::{check_annotation, index_search, search_genome};
+use kbrecondo::window::Coordinates;
+use kbrecondo::{AnnotationRecord, Error, GeneHits, Genome, Result};
+use std::env;
+use std::io::{stdin, Write};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
-use std::{env, usize};
+use std::process;
+use std::time::{Duration, Instant};
+
+/// Searches the region around annotated genes for a sequence.
+///
+/// Genomes and annotations are read from the `genomes` and `annotations`
+/// directories of the current directory, laid out as on
+/// https://data.legumeinfo.org/Medicago/truncatula/, unless a config file
+/// says otherwise.
+#[derive(Parser)]
+#[command(name = "kbrecondo", version, after_help = AFTER_HELP)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args, Default)]
+struct GlobalArgs {
+    /// Config file to use instead of ~/.config/kbrecondo/config.toml and
+    /// ./kbrecondo.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory holding one directory per genotype with its genome
+    #[arg(long, global = true)]
+    genomes: Option<PathBuf>,
+    /// Directory holding one directory per genotype with its annotations
+    #[arg(long, global = true)]
+    annotations: Option<PathBuf>,
+}
+
+const AFTER_HELP: &str = "\
+Exit codes: 0 success, 2 bad arguments, 3 a file could not be read or written,
+4 a file could not be parsed, 5 a file parsed but is inconsistent.
+
+The old positional form is still accepted:
+    kbrecondo [genotype] [window] [search] [output] [sequence] [species] [-n|-f|-m|-i] [mismatches]";
+
+#[derive(Subcommand)]
+enum Command {
+    /// Search the window around every gene for a sequence
+    Search(Box<SearchArgs>),
+    /// Test whether motifs are found more often around a gene set than
+    /// around a background
+    Enrich(Box<EnrichArgs>),
+    /// Build the FM-index of a genome used by `search --index`
+    Index(DataArgs),
+    /// List the genotypes, assemblies and sequence types in the data
+    /// directories
+    Inventory(InventoryArgs),
+}
+
+#[derive(Args)]
+struct InventoryArgs {
+    /// Only list this genotype
+    #[arg(short, long)]
+    genotype: Option<String>,
+    /// Only list this species, its templates are used if the config has any
+    #[arg(short, long)]
+    species: Option<String>,
+}
+
+/// Which genome and annotation to use.
+#[derive(Args, Clone)]
+struct DataArgs {
+    /// Genotype, the first dot separated part of its directory name (e.g. A17)
+    #[arg(short, long)]
+    genotype: Option<String>,
+    /// Species tag at the start of the file names [default: medtr]
+    #[arg(short, long)]
+    species: Option<String>,
+    /// Annotation whose genes are searched around (cds, mrna, gene, ...)
+    /// [default: cds]
+    #[arg(short = 't', long)]
+    seq_type: Option<String>,
+    /// Assembly version of the genotype (e.g. gnm5), needed when there is more
+    /// than one
+    #[arg(short, long)]
+    assembly: Option<String>,
+    /// Annotation version of the assembly (e.g. ann1_6), needed when there is
+    /// more than one
+    #[arg(long)]
+    annotation_version: Option<String>,
+    /// Genome FASTA to use instead of looking it up by genotype
+    #[arg(long)]
+    genome: Option<PathBuf>,
+    /// Annotation FASTA to use instead of looking it up by genotype
+    #[arg(long)]
+    annotation: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    #[command(flatten)]
+    data: DataArgs,
+    /// Sequence to search for
+    #[arg(short, long, required_unless_present = "pattern_fasta")]
+    pattern: Option<String>,
+    /// FASTA file holding the sequence to search for
+    #[arg(short = 'f', long, visible_alias = "query", conflicts_with = "pattern")]
+    pattern_fasta: Option<PathBuf>,
+    /// Bases searched on each side of a gene [default: 1000]
+    #[arg(short, long)]
+    window: Option<usize>,
+    /// Substitutions allowed in each occurrence [default: 0]
+    #[arg(short = 'k', long)]
+    mismatches: Option<usize>,
+    /// Positions in csv and JSON output: 1 (1 based inclusive, as the
+    /// annotation headers) or 0 (0 based half open). BED is always 0 based
+    /// and GFF3 and FASTA headers 1 based [default: 1]
+    #[arg(long)]
+    coordinates: Option<String>,
+    /// Genes to search: a csv or tsv with a header row, or one gene per line.
+    /// `-` reads the list from stdin
+    #[arg(short = 'l', long)]
+    gene_list: Option<PathBuf>,
+    /// Column of the gene list holding the genes, a header name or a number
+    /// counted from 1
+    #[arg(long, default_value = "1", requires = "gene_list")]
+    gene_column: String,
+    /// Columns of the gene list copied to the output as labels, by name or
+    /// number, comma separated [default: every other column]
+    #[arg(long, value_delimiter = ',', requires = "gene_list")]
+    label_columns: Vec<String>,
+    /// How gene list entries are matched: exact, prefix, regex or contains
+    /// (the part after the last _ anywhere in the field, as the old -m did)
+    #[arg(long, default_value = "exact", requires = "gene_list")]
+    match_mode: String,
+    /// Annotation field the gene list is matched against: id, gn or locus
+    #[arg(long, default_value = "gn", requires = "gene_list")]
+    match_field: String,
+    /// Search an FM-index of the genome instead of scanning every window,
+    /// built and saved next to the genome as <genome>.fmi on first use and
+    /// again when the genome changes.
+    /// Characters other than a, c, g and t are all treated as n
+    #[arg(long, conflicts_with = "mismatches")]
+    index: bool,
+    /// How the annotation headers are written: auto, legumeinfo, ncbi,
+    /// ensembl or phytozome [default: auto]
+    #[arg(long)]
+    dialect: Option<String>,
+    /// Regex reading the annotation headers, with the named groups chr,
+    /// begin, end and strand and optionally id, gn, locus and def
+    #[arg(long, conflicts_with = "dialect")]
+    header_regex: Option<String>,
+    /// Search every genotype in the data directories and write a gene ×
+    /// genotype table of hit counts and a presence/absence summary instead of
+    /// the hits
+    #[arg(long, conflicts_with_all = ["genotype", "genome", "annotation"])]
+    all_genotypes: bool,
+    /// Annotation field genes are lined up by across genotypes: id, gn or
+    /// locus
+    #[arg(long, default_value = "gn", requires = "all_genotypes")]
+    gene_key: String,
+    /// OrthoFinder Orthogroups.tsv, or two columns of gene and orthogroup,
+    /// to count the genes of an orthogroup as one row
+    #[arg(long, requires = "all_genotypes")]
+    orthogroups: Option<PathBuf>,
+    /// Also write one row per searched gene, with hit counts and the hit
+    /// closest to the transcription start, to <name>_..._summary.csv
+    #[arg(long, conflicts_with = "all_genotypes")]
+    summary: bool,
+    /// Output is written to <name>_<query>_<seq type>_<species>.csv, the
+    /// query is the pattern or the first word of the fasta header
+    #[arg(short, long, default_value = "kbrecondo")]
+    name: String,
+    /// Write the hits (or the matrix of --all-genotypes) here instead, `-`
+    /// for stdout. Other outputs are named after it
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Overwrite output files that already exist
+    #[arg(long)]
+    force: bool,
+    /// Gzip every output file, adding .gz to their names. Names given with
+    /// --output that end in .gz are always gzipped
+    #[arg(long)]
+    gzip: bool,
+    /// Format of the hits: csv, bed (BED6) or gff3 for loading into a
+    /// genome browser, or jsonl (one JSON object per hit and line)
+    #[arg(long, default_value = "csv", conflicts_with = "all_genotypes")]
+    format: String,
+    /// Also write the parameters, input files, counts and timings of the run
+    /// to <name>_..._run.json
+    #[arg(long)]
+    run_summary: bool,
+    /// Also write the window of every searched gene, turned to the strand of
+    /// the gene, to <name>_..._windows.fasta
+    #[arg(long, conflicts_with = "all_genotypes")]
+    extract_windows: bool,
+    /// Also write every hit with --flank bases on both sides, turned to the
+    /// strand of its gene, to <name>_..._hits.fasta
+    #[arg(long, conflicts_with = "all_genotypes")]
+    extract_hits: bool,
+    /// Bases of context on each side of a hit for --extract-hits
+    #[arg(long, default_value_t = 20, requires = "extract_hits")]
+    flank: usize,
+    /// Add the matched sequence and the bases before and after it, read in
+    /// the direction of the gene, to every csv or jsonl hit
+    #[arg(long, conflicts_with = "all_genotypes")]
+    show_match: bool,
+    /// Bases before the match for --show-match, upstream on the gene strand
+    #[arg(long, default_value_t = 10, requires = "show_match")]
+    left_flank: usize,
+    /// Bases after the match for --show-match
+    #[arg(long, default_value_t = 10, requires = "show_match")]
+    right_flank: usize,
+}
+
+#[derive(Args)]
+struct EnrichArgs {
+    #[command(flatten)]
+    data: DataArgs,
+    /// Motifs to test, comma separated
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        required_unless_present = "pattern_fasta"
+    )]
+    pattern: Vec<String>,
+    /// FASTA file with a motif in each record, named by the first word of
+    /// its header
+    #[arg(short = 'f', long, conflicts_with = "pattern")]
+    pattern_fasta: Option<PathBuf>,
+    /// Bases searched on each side of a gene [default: 1000]
+    #[arg(short, long)]
+    window: Option<usize>,
+    /// Substitutions allowed in each occurrence [default: 0]
+    #[arg(short = 'k', long)]
+    mismatches: Option<usize>,
+    /// Target genes, a gene list as for `search --gene-list`
+    #[arg(short = 'l', long)]
+    gene_list: PathBuf,
+    /// Column of the gene lists holding the genes, a header name or a number
+    /// counted from 1
+    #[arg(long, default_value = "1")]
+    gene_column: String,
+    /// How gene list entries are matched: exact, prefix, regex or contains
+    #[arg(long, default_value = "exact")]
+    match_mode: String,
+    /// Annotation field the gene lists are matched against: id, gn or locus
+    #[arg(long, default_value = "gn")]
+    match_field: String,
+    /// What the targets are compared with: all (every other gene of the
+    /// annotation), list (the genes of --background-list), shuffle (the
+    /// target windows shuffled) or markov (sequences from a Markov model of
+    /// the target windows) [default: all, or list with --background-list]
+    #[arg(long)]
+    background: Option<String>,
+    /// Background genes, a gene list read like --gene-list. Target genes in
+    /// it are left out
+    #[arg(long)]
+    background_list: Option<PathBuf>,
+    /// Sequences generated per target window for the shuffle and markov
+    /// backgrounds
+    #[arg(long, default_value_t = 10)]
+    samples: usize,
+    /// Bases each base depends on in the markov background
+    #[arg(long, default_value_t = 2)]
+    markov_order: usize,
+    /// Seed of the shuffle and markov backgrounds
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+    /// hypergeometric (target genes with hits among all genes with hits) or
+    /// binomial (target hits at the background rate per base)
+    #[arg(long, default_value = "hypergeometric")]
+    test: String,
+    /// Correction of the p-values across motifs: bh (Benjamini-Hochberg),
+    /// bonferroni or none
+    #[arg(long, default_value = "bh")]
+    correction: String,
+    /// How the annotation headers are written: auto, legumeinfo, ncbi,
+    /// ensembl or phytozome [default: auto]
+    #[arg(long)]
+    dialect: Option<String>,
+    /// Regex reading the annotation headers, see `search --header-regex`
+    #[arg(long, conflicts_with = "dialect")]
+    header_regex: Option<String>,
+    /// Output is written to <name>_<seq type>_<species>_enrichment.csv
+    #[arg(short, long, default_value = "kbrecondo")]
+    name: String,
+    /// Write the table here instead, `-` for stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Overwrite an output file that already exists
+    #[arg(long)]
+    force: bool,
+}
 
 fn main() {
-    env::set_var("RUST_BACKTRACE", "0");
-
-    let genotype = env::args()
-        .nth(1)
-        .expect("please enter a valid genome name (arg 1) or type -help");
-    if genotype == "-help" {
-        println!("
-Kbrecondo version v1.0.0
-
-Description: searches fastas for sequences in a window with several search parameters. Hard coded for the Medicago truncatula 
-             genome and annotations as formatted in:
-             https://data.legumeinfo.org/Medicago/truncatula/
-
-Format: kbrecondo [genotype] [window] [search] [output] [sequence] [species] [option]
-
-Example: 
-
-Manually input search:
-    kbrecondo A17 1000 ACTAC test cds medtr -n
-
-Fasta input search:
-    kbrecondo A17 1000 search.fasta test cds medtr -f
-
-Flags:
-    -n: normal search, runs a search in the format of the manually input search
-    -f: use a fasta as the sequence input (currently only takes one sequence but in the future will take mutliple for bulk search)
-    -m: asks for a csv containing gene ids to use to guide searching. The first column of the csv must have the gene ids. the ids
-        will be matched based on what is found in the headers of the fasta
-
-");
-        std::process::exit(3);
-    }
-    let size_string = env::args()
-        .nth(2)
-        .expect("please enter the size for the search window (arg 2)");
-    let size = size_string.trim().parse::<i32>().unwrap();
-    let raw_pattern = env::args()
-        .nth(3)
-        .expect("please enter the pattern to search for (arg 3)");
-    let name = env::args()
-        .nth(4)
-        .expect("please enter the name for the csv (arg 4)");
-    let seq_type = env::args()
-        .nth(5)
-        .expect("please enter the type of sequence you are searching around (arg 5)");
-    let species = env::args()
-        .nth(6)
-        .expect("please enter the species tag (arg 6)");
-    let option = env::args()
-        .nth(7)
-        .expect("please enter a option tag (arg 7)");
-
-    let top_dir = env::current_dir().expect("bad top dir");
-    let genomes = create_full_path(top_dir.clone(), String::from("genomes"));
-    let annotation = create_full_path(top_dir.clone(), String::from("annotations"));
-    let dir_geno = get_name(
-        genotype.clone(),
-        genomes.clone(),
-        seq_type.clone(),
-        species.clone(),
-    );
-    let dir_anno = get_name(
-        genotype.clone(),
-        annotation.clone(),
-        seq_type.clone(),
-        species.clone(),
+    let result = match env::args().nth(1) {
+        Some(first) if is_legacy(&first) => {
+            legacy_args().and_then(|args| search(args, GlobalArgs::default()))
+        }
+        _ => {
+            let cli = Cli::parse();
+            match cli.command {
+                Command::Search(args) => search(*args, cli.global),
+                Command::Enrich(args) => enrich(*args, cli.global),
+                Command::Index(args) => index(args, cli.global),
+                Command::Inventory(args) => inventory(args, cli.global),
+            }
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("kbrecondo: {}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+// anything that is not a subcommand or a flag is the genotype of the old
+// positional form
+fn is_legacy(first: &str) -> bool {
+    first == "-help"
+        || !(first.starts_with('-')
+            || ["search", "enrich", "index", "inventory", "help"].contains(&first))
+}
+
+fn number_arg(value: &str, what: &str) -> Result<usize> {
+    value
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| Error::config(format!("{} must be a whole number, got {:?}", what, value)))
+}
+
+/// Translates `kbrecondo [genotype] [window] [search] [output] [sequence]
+/// [species] [option] [mismatches]` into the arguments of `search`.
+fn legacy_args() -> Result<SearchArgs> {
+    let args: Vec<String> = env::args().collect();
+    if args[1] == "-help" {
+        Cli::parse_from(["kbrecondo", "--help"]);
+    }
+    if args.len() < 8 {
+        return Err(Error::config(
+            "expected [genotype] [window] [search] [output] [sequence] [species] [option], \
+             see kbrecondo --help",
+        ));
+    }
+    eprintln!("kbrecondo: positional arguments are deprecated, see kbrecondo search --help");
+    eprintln!(
+        "kbrecondo: positional arguments write 0 based half open coordinates, \
+         begin is now one lower than in the annotation header"
     );
+    let option = args[7].as_str();
+    if !["-n", "-f", "-m", "-i"].contains(&option) {
+        return Err(Error::config(format!(
+            "unknown option {:?}, expected -n, -f, -m or -i",
+            option
+        )));
+    }
+    let mut gene_list = None;
+    if option == "-m" {
+        let mut csv_path = String::new();
+        println!("\nPlease enter path to csv (or use kbrecondo search --gene-list)");
+        stdin()
+            .read_line(&mut csv_path)
+            .map_err(|err| Error::io("stdin".as_ref(), err))?;
+        gene_list = Some(PathBuf::from(csv_path.trim()));
+    }
+    let (pattern, pattern_fasta) = if option == "-f" {
+        (None, Some(PathBuf::from(&args[3])))
+    } else {
+        (Some(args[3].clone()), None)
+    };
+    Ok(SearchArgs {
+        data: DataArgs {
+            genotype: Some(args[1].clone()),
+            species: Some(args[6].clone()),
+            seq_type: Some(args[5].clone()),
+            assembly: None,
+            annotation_version: None,
+            genome: None,
+            annotation: None,
+        },
+        pattern,
+        pattern_fasta,
+        window: Some(number_arg(&args[2], "window size (arg 2)")?),
+        mismatches: match args.get(8) {
+            Some(m) => Some(number_arg(m, "mismatches (arg 8)")?),
+            None => None,
+        },
+        // the positional form always wrote 0 based offsets
+        coordinates: Some(String::from("0")),
+        gene_list,
+        gene_column: String::from("1"),
+        label_columns: Vec::new(),
+        match_mode: String::from("contains"),
+        match_field: String::from("gn"),
+        index: option == "-i",
+        dialect: None,
+        header_regex: None,
+        all_genotypes: false,
+        gene_key: String::from("gn"),
+        orthogroups: None,
+        summary: false,
+        name: args[4].clone(),
+        output: None,
+        force: false,
+        gzip: false,
+        format: String::from("csv"),
+        run_summary: false,
+        extract_windows: false,
+        extract_hits: false,
+        flank: 20,
+        show_match: false,
+        left_flank: 10,
+        right_flank: 10,
+    })
+}
 
-    let full_geno = create_full_path(genomes.clone(), dir_geno.clone());
-    let full_anno = create_full_path(annotation.clone(), dir_anno.clone());
+/// Genotype, species and files to use, from the flags, the config files and
+/// the built in defaults in that order. Files given with `--genome` or
+/// `--annotation` are used as they are.
+struct Data {
+    args: DataArgs,
+    species: String,
+    seq_type: String,
+    genomes: PathBuf,
+    annotations: PathBuf,
+    genome_template: String,
+    annotation_template: String,
+}
 
-    let mut pattern = String::new();
-    let mut pat_identifier = String::new();
+impl Data {
+    fn resolve(data: DataArgs, global: &GlobalArgs, config: &Config) -> Data {
+        let species = data
+            .species
+            .clone()
+            .or_else(|| config.defaults.species.clone())
+            .unwrap_or_else(|| String::from("medtr"));
+        let seq_type = data
+            .seq_type
+            .clone()
+            .or_else(|| config.defaults.seq_type.clone())
+            .unwrap_or_else(|| String::from("cds"));
+        Data {
+            genomes: global
+                .genomes
+                .clone()
+                .unwrap_or_else(|| config.genomes(&species)),
+            annotations: global
+                .annotations
+                .clone()
+                .unwrap_or_else(|| config.annotations(&species)),
+            genome_template: config.genome_template(&species),
+            annotation_template: config.annotation_template(&species),
+            args: data,
+            species,
+            seq_type,
+        }
+    }
 
-    if option == "-f" {
-        let search_path = create_full_path(top_dir.clone(), raw_pattern.clone());
-        assert!(Path::new(&search_path).exists());
-        let search_fasta = read_search_fasta_single(search_path);
-        let search_key = search_fasta.keys().next().unwrap();
-        let fasta_vals = search_fasta.get_key_value(search_key).unwrap().1;
-        pat_identifier.push_str(search_key);
-        pattern.push_str(fasta_vals);
+    fn names(&self, flag: &str) -> Result<Names> {
+        let genotype = self
+            .args
+            .genotype
+            .clone()
+            .ok_or_else(|| Error::config(format!("please give --genotype or --{}", flag)))?;
+        Ok(Names {
+            species: Some(self.species.clone()),
+            genotype: Some(genotype),
+            seq_type: Some(self.seq_type.clone()),
+            assembly: self.args.assembly.clone(),
+            annotation: self.args.annotation_version.clone(),
+        })
     }
 
-    if option == "-m" {
-        pattern.push_str(&raw_pattern);
-        pat_identifier.push_str(&raw_pattern);
+    /// The header dialect from the flags or the config of the species.
+    fn dialect(&self, config: &Config, flag: Option<&str>, regex: Option<&str>) -> Result<Dialect> {
+        let entry = config.species.get(&self.species);
+        if let Some(regex) = regex.or(entry.and_then(|e| e.header_regex.as_deref())) {
+            return Dialect::custom(regex);
+        }
+        match flag.or(entry.and_then(|e| e.header_dialect.as_deref())) {
+            Some(name) => Dialect::from_name(name),
+            None => Ok(Dialect::Auto),
+        }
     }
 
-    if option == "-n" {
-        pattern.push_str(&raw_pattern);
-        pat_identifier.push_str(&raw_pattern);
+    fn genome(&self) -> Result<TemplateMatch> {
+        match &self.args.genome {
+            Some(genome) => Ok(TemplateMatch {
+                path: genome.clone(),
+                fields: Vec::new(),
+            }),
+            None => find_one(&self.genomes, &self.genome_template, &self.names("genome")?),
+        }
     }
 
-    let pattern_size = pattern.len();
-    let test_size = size as usize;
-    if test_size < pattern_size {
-        println!("Window Size (arg 2) must be larger than the length of the search pattern");
-        std::process::exit(3);
+    /// The annotation of the assembly `genome` was found for.
+    fn annotation(&self, genome: &TemplateMatch) -> Result<PathBuf> {
+        if let Some(annotation) = &self.args.annotation {
+            return Ok(annotation.clone());
+        }
+        let mut names = self.names("annotation")?;
+        if names.assembly.is_none() {
+            names.assembly = genome.field("assembly").map(String::from);
+        }
+        let found = find_one(&self.annotations, &self.annotation_template, &names)?;
+        eprintln!(
+            "Using annotation {}",
+            found.path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        Ok(found.path)
     }
+}
 
-    assert!(Path::new(&full_anno).exists());
-    assert!(Path::new(&full_geno).exists());
+fn index(args: DataArgs, global: GlobalArgs) -> Result<()> {
+    let config = Config::load(global.config.as_deref())?;
+    let genome = Data::resolve(args, &global, &config).genome()?.path;
+    eprintln!("Indexing {}", genome.display());
+    let start = Instant::now();
+    let index = GenomeIndex::build_for(&genome)?;
+    eprintln!("It took {:?} to build the index", start.elapsed());
+    let index_path = GenomeIndex::path_for(&genome);
+    eprintln!("Saving index {:?}", index_path);
+    index.save(index_path)
+}
 
-    let decogeno = read_fasta(full_geno);
-    let decoanno = read_fasta(full_anno);
+fn inventory(args: InventoryArgs, global: GlobalArgs) -> Result<()> {
+    let config = Config::load(global.config.as_deref())?;
+    let species = args
+        .species
+        .clone()
+        .or_else(|| config.defaults.species.clone())
+        .unwrap_or_else(|| String::from("medtr"));
+    let names = Names {
+        species: args.species,
+        genotype: args.genotype,
+        ..Names::default()
+    };
+    let genomes = global.genomes.unwrap_or_else(|| config.genomes(&species));
+    let annotations = global
+        .annotations
+        .unwrap_or_else(|| config.annotations(&species));
+    let found = Inventory::scan(
+        &genomes,
+        &config.genome_template(&species),
+        &annotations,
+        &config.annotation_template(&species),
+        &names,
+    )?;
 
-    let akeys = decoanno.keys();
-    let gkeys = decogeno.keys();
+    let mut rows: Vec<[String; 6]> = vec![[
+        "species",
+        "genotype",
+        "assembly",
+        "annotation",
+        "seq types",
+        "genome",
+    ]
+    .map(String::from)];
+    for genome in &found.genomes {
+        let (species, genotype, assembly) = genome.key.clone();
+        let file = genome.paths[0].file_name().unwrap_or_default();
+        let file = file.to_string_lossy().to_string();
+        let anns: Vec<_> = found
+            .annotations
+            .iter()
+            .filter(|ann| ann.key == genome.key)
+            .collect();
+        if anns.is_empty() {
+            rows.push([species, genotype, assembly, "-".into(), "-".into(), file]);
+            continue;
+        }
+        for ann in anns {
+            let seq_types: Vec<&str> = ann.seq_types.keys().map(|t| t.as_str()).collect();
+            rows.push([
+                species.clone(),
+                genotype.clone(),
+                assembly.clone(),
+                ann.annotation.clone(),
+                seq_types.join(","),
+                file.clone(),
+            ]);
+        }
+    }
+    for ann in &found.annotations {
+        if !found.genomes.iter().any(|genome| genome.key == ann.key) {
+            let (species, genotype, assembly) = ann.key.clone();
+            let seq_types: Vec<&str> = ann.seq_types.keys().map(|t| t.as_str()).collect();
+            rows.push([
+                species,
+                genotype,
+                assembly,
+                ann.annotation.clone(),
+                seq_types.join(","),
+                "-".into(),
+            ]);
+        }
+    }
 
-    // std::process::exit(1);
-    let mut csv_name = name;
-    csv_name.push_str("_");
-    csv_name.push_str(&pat_identifier);
-    csv_name.push_str("_");
-    csv_name.push_str(&seq_type);
-    csv_name.push_str("_");
-    csv_name.push_str(&species);
-    csv_name.push_str(".csv");
-    let csv_path = create_full_path(top_dir.clone(), csv_name.clone());
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
 
-    if Path::new(&csv_path).exists() {
-        println!(
-            "{} exists, Please remove the file from the directory so it is not overwritten",
-            csv_name.clone()
-        );
-    } else {
-        let mut wrt = Writer::from_path(csv_path).expect("Did not write csv");
-        wrt.write_record(&[
-            "id",
-            "length",
-            "begin",
-            "end",
-            "strand",
-            "occurance.location",
-            "info",
-        ])
-        .expect("Did not write fist line");
-
-        println!("Now Searching for {}", &pattern);
-
-        if option == "-m" {
-            let mut csv_path = String::new();
-            println!("\nPlease enter path to csv");
-            stdin()
-                .read_line(&mut csv_path)
-                .expect("Did not enter a path to csv");
-            let csv_path = csv_path.trim();
-            let csv_path = create_full_path(top_dir.clone(), csv_path.to_string());
-            println!("{:?}", csv_path);
-            let search_map = read_csv_first_col(csv_path);
-            search(
-                decogeno.clone(),
-                akeys,
-                gkeys,
-                search_map.keys(),
-                size,
-                pattern,
-                wrt,
-                option,
-            );
-        } else {
-            let search_map: HashMap<String, String> = HashMap::new();
-            search(
-                decogeno.clone(),
-                akeys,
-                gkeys,
-                search_map.keys(),
-                size,
-                pattern,
-                wrt,
-                option,
-            );
+    let problems = found.problems();
+    if !problems.is_empty() {
+        println!();
+        for problem in problems {
+            println!("warning: {}", problem);
         }
     }
+    Ok(())
 }
 
-fn search(
-    decogeno: HashMap<String, String>,
-    akeys: Keys<'_, String, String>,
-    gkeys: Keys<'_, String, String>,
-    search_map: Keys<'_, String, String>,
+/// Errors if `name` exists, so earlier results are not overwritten.
+fn output_path(name: String, force: bool) -> Result<PathBuf> {
+    let path = PathBuf::from(&name);
+    if path.exists() && !force && name != "-" {
+        return Err(Error::config(format!(
+            "{} exists, Please remove the file from the directory or use --force so it is overwritten",
+            name
+        )));
+    }
+    Ok(path)
+}
 
-    size: i32,
-    pattern: String,
-    mut wrt: Writer<File>,
-    option: String,
-) {
-    let start = Instant::now();
-    for gk in gkeys {
-        println!("\nSearching: {:?}", gk.to_string());
-        let info_geno = get_info(parse_header(gk.to_string()));
-        let acc = get_element(info_geno.clone(), String::from("acc"));
-        let chromosome = acc.split("=").last().unwrap();
-
-        for ak in akeys.clone() {
-            let info_anno = get_info(parse_header(ak.to_string()));
-
-            let chrom = get_element(info_anno.clone(), String::from("chr"));
-            let spchrom = chrom.split("=").last().unwrap();
-            let chrom_len = get_element(info_geno.clone(), String::from("len"));
-            let spchrom_len = chrom_len.split("=").last().unwrap();
-            let chrom_len_num = spchrom_len.to_string().parse::<i32>().unwrap();
-
-            if option == "-m" {
-                let gn = get_element(info_anno.clone(), String::from("gn"));
-                for search in search_map.clone() {
-                    let search_test = search.split("_").last().unwrap();
-                    if gn.trim().contains(search_test) && !search_test.is_empty() {
-                        if chromosome == spchrom {
-                            let chrom_seq = decogeno.get_key_value(gk).unwrap().1;
-                            let chrom_seq_len = chrom_seq.len() as i32;
-                            assert!(chrom_seq_len == chrom_len_num);
-                            let header = &info_anno[0];
-                            let strand = get_element(info_anno.clone(), String::from("strand"));
-                            let begend = get_begin_end(info_anno.clone());
-                            let window = build_window(begend[0], begend[1], size, chrom_seq_len);
-                            let occrances =
-                                search_seq(chrom_seq.to_string(), window, pattern.clone(), strand);
-                            write_csv(&mut wrt, info_anno.clone(), occrances);
-                        }
-                    } else {
-                        continue;
-                    }
-                }
-            } else {
-                if chromosome == spchrom {
-                    let chrom_seq = decogeno.get_key_value(gk).unwrap().1;
-                    let chrom_seq_len = chrom_seq.len() as i32;
-                    assert!(chrom_seq_len == chrom_len_num);
-                    let header = &info_anno[0];
-                    let strand = get_element(info_anno.clone(), String::from("strand"));
-                    let begend = get_begin_end(info_anno.clone());
-                    let window = build_window(begend[0], begend[1], size, chrom_seq_len);
-                    let occrances =
-                        search_seq(chrom_seq.to_string(), window, pattern.clone(), strand);
-                    write_csv(&mut wrt, info_anno.clone(), occrances);
+fn check_pattern(pattern: &str, window: usize) -> Result<()> {
+    if let Some(bad) = pattern
+        .chars()
+        .find(|nuc| !"acgtn".contains(nuc.to_ascii_lowercase()))
+    {
+        return Err(Error::config(format!(
+            "the search pattern may only contain a, c, g, t and n, found {:?}",
+            bad
+        )));
+    }
+    if window < pattern.len() {
+        return Err(Error::config(
+            "the window must be larger than the length of the search pattern",
+        ));
+    }
+    Ok(())
+}
+
+// characters other than letters, digits, `.`, `-` and `_` become `_` and
+// long patterns are cut, so the query can go into a file name
+fn file_name_part(text: &str) -> String {
+    let mut part: String = text
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || ".-_".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .take(40)
+        .collect();
+    while part.starts_with('.') {
+        part.replace_range(..1, "_");
+    }
+    part
+}
+
+/// Names the output files after --name, or after --output when it is a
+/// file, see --force and --gzip.
+struct Outputs {
+    base: String,
+    force: bool,
+    gzip: bool,
+}
+
+impl Outputs {
+    /// `<base><suffix>`, with .gz added when gzipping.
+    fn path(&self, suffix: &str) -> Result<PathBuf> {
+        let mut name = format!("{}{}", self.base, suffix);
+        if self.gzip {
+            name.push_str(".gz");
+        }
+        output_path(name, self.force)
+    }
+
+    /// The path given with --output, or else `<base><suffix>`.
+    fn main_path(&self, output: Option<&Path>, suffix: &str) -> Result<PathBuf> {
+        match output {
+            Some(path) if path == Path::new("-") => Ok(path.to_path_buf()),
+            Some(path) => {
+                let mut name = path.to_string_lossy().to_string();
+                if self.gzip && !name.ends_with(".gz") {
+                    name.push_str(".gz");
                 }
+                output_path(name, self.force)
             }
-        }
-        let dur = start.elapsed();
-        let dur_min = dur.as_secs() / 60;
-        let dur_rem = dur.as_secs() % 60;
-        println!(
-            "Completed in {:?} minutes and {:?} seconds",
-            dur_min, dur_rem
-        )
-    }
-}
-
-fn read_csv_first_col<P>(filename: P) -> HashMap<String, String>
-where
-    P: AsRef<Path>,
-{
-    let mut id_map: HashMap<String, String> = HashMap::new();
-    let mut rdr = Reader::from_path(filename).expect("Csv does not exist");
-    for record in rdr.records() {
-        let rec = record.expect("268, could not read record");
-        let id = &rec[0];
-        let sudoname = &rec[1];
-        id_map.insert(id.to_string(), sudoname.to_string());
-    }
-
-    id_map
-}
-
-fn read_search_fasta_single<P>(filename: P) -> HashMap<String, String>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename).expect("Could not open file");
-    let buf = BufReader::new(file);
-    let mut fasta = HashMap::new();
-    let mut curid = String::new();
-    let mut curseq = String::new();
-    for line in buf.lines() {
-        let line = line.expect("Could not read line");
-        if line.starts_with('>') {
-            curid = line[..].trim().to_string();
-        } else {
-            curseq.push_str(line.trim());
+            None => self.path(suffix),
         }
     }
-    fasta.insert(curid.clone(), curseq.clone());
-    fasta
 }
 
-fn get_begin_end(info: Vec<String>) -> Vec<i32> {
-    let info_split = info[0].split(" ");
-    let mut begend: Vec<i32> = Vec::new();
-    let mut begin = String::new();
-    let mut end = String::new();
-    let mut misc = String::new();
+fn search(args: SearchArgs, global: GlobalArgs) -> Result<()> {
+    let start = Instant::now();
+    let config = Config::load(global.config.as_deref())?;
+    let window = args.window.or(config.defaults.window).unwrap_or(1000);
+    let format = OutputFormat::parse(&args.format)?;
+    let mismatches = args.mismatches.or(config.defaults.mismatches).unwrap_or(0);
+    let coordinates = match args
+        .coordinates
+        .as_ref()
+        .or(config.defaults.coordinates.as_ref())
+    {
+        Some(coordinates) => Coordinates::parse(coordinates)?,
+        None => Coordinates::default(),
+    };
+    let data = Data::resolve(args.data.clone(), &global, &config);
+    let dialect = data.dialect(
+        &config,
+        args.dialect.as_deref(),
+        args.header_regex.as_deref(),
+    )?;
 
-    for fo in info_split {
-        let fon = fo.split("=").next().unwrap();
-        let fol = fo.split("=").last().unwrap();
-        match fon {
-            "begin" => begin.push_str(&fol),
-            "end" => end.push_str(&fol),
-            _ => misc.push_str(&fol),
-        }
+    let (pat_identifier, pattern) = match (&args.pattern, &args.pattern_fasta) {
+        (_, Some(search_path)) => read_search_fasta_single(search_path)?,
+        (Some(pattern), None) => (pattern.clone(), pattern.clone()),
+        (None, None) => return Err(Error::config("please give --pattern or --pattern-fasta")),
+    };
+
+    check_pattern(&pattern, window)?;
+    if args.index && mismatches > 0 {
+        return Err(Error::config(
+            "mismatches are not supported by the index search",
+        ));
     }
 
-    let ibegin = begin.to_string().trim().parse::<i32>().unwrap();
-    let iend = end.to_string().trim().parse::<i32>().unwrap();
-
-    begend.push(ibegin);
-    begend.push(iend);
-    assert!(begend.len() == 2, "{:?} Did not pass", info[0]);
-    assert!(begend[0] < begend[1]);
-    begend
-}
-
-fn build_window(begin: i32, end: i32, window_size: i32, seq_len: i32) -> Vec<i32> {
-    let mut window: Vec<i32> = Vec::new();
-    if begin < window_size {
-        let left_bound: i32 = (begin + 1) - begin;
-        window.push(left_bound);
-        if end + window_size > seq_len {
-            let window_end_diff = (end + window_size) - seq_len;
-            let right_bound: i32 = (end + window_size) - window_end_diff;
-            window.push(right_bound);
-        } else {
-            let right_bound: i32 = end + window_size;
-            window.push(right_bound);
+    // the query id is the first word of the fasta header
+    let query = pat_identifier.trim_start_matches('>');
+    let query = query.split_whitespace().next().unwrap_or(&pattern);
+
+    let base = match &args.output {
+        Some(path) if path != Path::new("-") => {
+            let path = path.to_string_lossy();
+            let path = path.trim_end_matches(".gz");
+            Path::new(path)
+                .with_extension("")
+                .to_string_lossy()
+                .to_string()
         }
-    } else {
-        let left_bound: i32 = begin - window_size;
-        window.push(left_bound);
-        if end + window_size > seq_len {
-            let window_end_diff = (end + window_size) - seq_len;
-            let right_bound: i32 = (end + window_size) - window_end_diff;
-            window.push(right_bound);
-        } else {
-            let right_bound: i32 = end + window_size;
-            window.push(right_bound);
+        _ => format!(
+            "{}_{}_{}_{}",
+            args.name,
+            file_name_part(query),
+            file_name_part(&data.seq_type),
+            file_name_part(&data.species)
+        ),
+    };
+    let outputs = Outputs {
+        base,
+        force: args.force,
+        gzip: args.gzip,
+    };
+
+    // read once, the list may come from stdin
+    let gene_list = match &args.gene_list {
+        Some(list_path) => Some(gene_matcher(
+            list_path,
+            &args.gene_column,
+            &args.label_columns,
+            &args.match_mode,
+            &args.match_field,
+        )?),
+        None => None,
+    };
+    let mut run = RunSummary::new(RunParameters {
+        query: query.to_string(),
+        pattern: pattern.clone(),
+        species: data.species.clone(),
+        seq_type: data.seq_type.clone(),
+        window,
+        mismatches,
+        coordinates: coordinates.name().to_string(),
+        index: args.index,
+        format: format.extension().to_string(),
+        gene_list: args.gene_list.clone(),
+        match_mode: args.gene_list.as_ref().map(|_| args.match_mode.clone()),
+        match_field: args.gene_list.as_ref().map(|_| args.match_field.clone()),
+    });
+    let run_path = match args.run_summary {
+        true => Some(outputs.path("_run.json")?),
+        false => None,
+    };
+
+    if args.all_genotypes {
+        let key = GeneKey {
+            field: Field::parse(&args.gene_key)?,
+            orthogroups: match &args.orthogroups {
+                Some(path) => {
+                    let groups = Orthogroups::read(path)?;
+                    eprintln!("Read {} genes in orthogroups", groups.groups.len());
+                    Some(groups)
+                }
+                None => None,
+            },
+        };
+        let matrix_path = outputs.main_path(args.output.as_deref(), "_matrix.csv")?;
+        let presence_path = outputs.path("_presence.csv")?;
+
+        let found = Inventory::scan(
+            &data.genomes,
+            &data.genome_template,
+            &data.annotations,
+            &data.annotation_template,
+            &Names {
+                species: Some(data.species.clone()),
+                seq_type: Some(data.seq_type.clone()),
+                assembly: args.data.assembly.clone(),
+                annotation: args.data.annotation_version.clone(),
+                ..Names::default()
+            },
+        )?;
+        let columns = pangenome::columns(&found, &data.seq_type)?;
+        if columns.is_empty() {
+            return Err(Error::config(format!(
+                "no genotype in {} has both a genome and a {} annotation",
+                data.genomes.display(),
+                data.seq_type
+            )));
+        }
+        let search = PangenomeSearch {
+            dialect,
+            gene_list: gene_list.as_ref(),
+            pattern: pattern.clone(),
+            window,
+            mismatches,
+            index: args.index,
+            key,
+        };
+        let (matrix, inputs) = pangenome::run(&columns, &search, |step| match step {
+            Progress::Started(column) => {
+                eprintln!("\nGenotype {}", column.name);
+                eprintln!("Using annotation {}", column.annotation.display());
+            }
+            Progress::Selected { records, selection } => {
+                if let Some(matcher) = search.gene_list {
+                    report_selection(matcher, records, selection, "gene list");
+                }
+            }
+            Progress::Index { genome, origin } => {
+                report_index(&GenomeIndex::path_for(genome), &origin)
+            }
+            Progress::Missing(missing) => report_missing(missing),
+            Progress::Finished(input) => print_duration(Duration::from_secs_f64(
+                input.read_seconds + input.search_seconds,
+            )),
+        })?;
+        for input in inputs {
+            run.add(input);
         }
-    }
-    window
-}
 
-fn get_element(info: Vec<String>, element: String) -> String {
-    let info = &info[0];
-    let info_sp = info.split(" ");
-    let mut element_type = String::new();
-    for i in info_sp {
-        if i.contains(&element) {
-            element_type.push_str(i);
+        let mut wrt = Writer::from_writer(Output::create(&matrix_path)?);
+        write_matrix(&mut wrt, &matrix).map_err(|err| Error::io(&matrix_path, err))?;
+        finish_csv(wrt, &matrix_path)?;
+        let mut wrt = Writer::from_writer(Output::create(&presence_path)?);
+        write_presence(&mut wrt, &matrix).map_err(|err| Error::io(&presence_path, err))?;
+        finish_csv(wrt, &presence_path)?;
+        run.outputs = vec![matrix_path, presence_path];
+        if let Some(run_path) = run_path {
+            run.total_seconds = start.elapsed().as_secs_f64();
+            write_run_json(&run_path, &run)?;
         }
+        return Ok(());
     }
-    element_type
-}
-
-fn minus_strand_invsersion(pat: String) -> String {
-    let mut inversion = String::new();
-    let pat_inv: String = pat.chars().rev().collect();
-    // instead of inveerting the entire sequence, just invert the and reverse it
-    for nuc in pat_inv.chars() {
-        match nuc {
-            'a' => inversion.push_str("t"),
-            'c' => inversion.push_str("g"),
-            't' => inversion.push_str("a"),
-            'g' => inversion.push_str("c"),
-            'n' => inversion.push_str("n"),
-            _ => println!("This is not a nucleotide, or n"),
+
+    let summary_path = match args.summary {
+        true => Some(outputs.path("_summary.csv")?),
+        false => None,
+    };
+    let windows_path = match args.extract_windows {
+        true => Some(outputs.path("_windows.fasta")?),
+        false => None,
+    };
+    let hits_path = match args.extract_hits {
+        true => Some(outputs.path("_hits.fasta")?),
+        false => None,
+    };
+    let csv_path =
+        outputs.main_path(args.output.as_deref(), &format!(".{}", format.extension()))?;
+    run.outputs.push(csv_path.clone());
+    let (results, input, genome) = find_hits(
+        &args,
+        &data,
+        &dialect,
+        gene_list.as_ref(),
+        &pattern,
+        window,
+        mismatches,
+    )?;
+    run.add(input);
+
+    // the index search never reads the genome
+    let genome = match (
+        genome,
+        args.show_match || args.extract_windows || args.extract_hits,
+    ) {
+        (None, true) => Some(load_genome(&run.inputs[0].genome)?),
+        (genome, _) => genome,
+    };
+    let contexts = |gene: &GeneHits| -> Vec<HitContext> {
+        let chrom = match (&genome, args.show_match) {
+            (Some(genome), true) => genome.get(&gene.record.chromosome),
+            _ => None,
+        };
+        match chrom {
+            Some(chrom) => HitContext::all(
+                chrom,
+                gene,
+                pattern.len(),
+                args.left_flank,
+                args.right_flank,
+            ),
+            None => Vec::new(),
         }
-    }
+    };
 
-    inversion
-}
-
-fn search_seq(seq: String, window: Vec<i32>, pattern: String, strand: String) -> Vec<String> {
-    let strand = strand.split("=").last().unwrap();
-    let mut occurances: Vec<String> = Vec::new();
-    if seq.len() > pattern.len() {
-        if strand == "-" {
-            let seq = seq.to_lowercase();
-            let pattern = pattern.to_lowercase();
-            let inversion = minus_strand_invsersion(pattern);
-            let pattern = inversion;
-            let bseq = seq.as_bytes();
-            let bpat = pattern.as_bytes();
-            let left_bound = window[0] as usize;
-            let right_bound = window[1] as usize;
-            let search_area = &bseq[left_bound..right_bound];
-
-            for i in 0..search_area.len() - bpat.len() {
-                if bpat == &search_area[i..i + bpat.len()] {
-                    let location = left_bound + i;
-                    occurances.push(location.to_string());
-                }
+    match format {
+        OutputFormat::Csv => {
+            let csv_error = |err: csv::Error| Error::io(&csv_path, err.into());
+            let mut wrt = Writer::from_writer(Output::create(&csv_path)?);
+            let mut header: Vec<&str> = CSV_HEADER.to_vec();
+            if args.show_match {
+                header.extend(CONTEXT_HEADER);
+            }
+            if let Some(matcher) = &gene_list {
+                header.extend(matcher.label_names.iter().map(|name| name.as_str()));
+            }
+            wrt.write_record(&header).map_err(csv_error)?;
+            for gene in &results {
+                let labels = match &gene_list {
+                    Some(matcher) => matcher.labels(&gene.record),
+                    None => Vec::new(),
+                };
+                write_csv_labelled(&mut wrt, gene, &labels, coordinates, &contexts(gene))
+                    .map_err(|err| Error::io(&csv_path, err))?;
             }
-        } else {
-            let seq = seq.to_lowercase();
-            let pattern = pattern.to_lowercase();
-            let bseq = seq.as_bytes();
-            let bpat = pattern.as_bytes();
-            let left_bound = window[0] as usize;
-            let right_bound = window[1] as usize;
-            let search_area = &bseq[left_bound..right_bound];
-
-            for i in 0..search_area.len() - bpat.len() {
-                if bpat == &search_area[i..i + bpat.len()] {
-                    let location = left_bound + i;
-                    occurances.push(location.to_string());
+            finish_csv(wrt, &csv_path)?;
+        }
+        OutputFormat::Bed | OutputFormat::Gff3 | OutputFormat::Jsonl => {
+            let mut out = Output::create(&csv_path)?;
+            let io_error = |err| Error::io(&csv_path, err);
+            let label_names: Vec<&str> = match &gene_list {
+                Some(matcher) => matcher.label_names.iter().map(|n| n.as_str()).collect(),
+                None => Vec::new(),
+            };
+            if format == OutputFormat::Gff3 {
+                writeln!(out, "{}", GFF3_HEADER).map_err(io_error)?;
+            }
+            let mut count = 0;
+            for gene in &results {
+                if format == OutputFormat::Bed {
+                    write_bed(&mut out, gene, pattern.len()).map_err(io_error)?;
+                    continue;
+                }
+                let labels = match &gene_list {
+                    Some(matcher) => matcher.labels(&gene.record),
+                    None => Vec::new(),
+                };
+                let labels: Vec<(&str, &str)> = label_names
+                    .iter()
+                    .zip(&labels)
+                    .map(|(name, value)| (*name, value.as_str()))
+                    .collect();
+                if format == OutputFormat::Jsonl {
+                    write_jsonl(&mut out, gene, query, &labels, coordinates, &contexts(gene))
+                        .map_err(io_error)?;
+                } else {
+                    write_gff3(&mut out, gene, pattern.len(), query, &labels, &mut count)
+                        .map_err(io_error)?;
                 }
             }
+            out.finish().map_err(io_error)?;
         }
-    } else {
-        occurances.push(String::from("pattern larger than sequence"));
-    }
-
-    occurances
-}
-
-fn write_csv(writer: &mut Writer<File>, info: Vec<String>, occurances: Vec<String>) {
-    let mut id = String::new();
-    let mut strand = String::new();
-    let mut length = String::new();
-    let mut begin = String::new();
-    let mut end = String::new();
-    let def_info = &info[1];
-    let mut misc = String::new();
-    let info = info[0].split(" ");
-    for i in info {
-        let sp1 = i.split("=").next().unwrap();
-        let sp2 = i.split("=").last().unwrap();
-        match sp1 {
-            "begin" => begin.push_str(sp2),
-            "end" => end.push_str(sp2),
-            "len" => length.push_str(sp2),
-            "strand" => strand.push_str(sp2),
-            "id" => id.push_str(sp2),
-            _ => misc.push_str(sp2),
+    }
+
+    if let Some(summary_path) = summary_path {
+        run.outputs.push(summary_path.clone());
+        let mut wrt = Writer::from_writer(Output::create(&summary_path)?);
+        let mut header: Vec<&str> = SUMMARY_HEADER.to_vec();
+        if let Some(matcher) = &gene_list {
+            header.extend(matcher.label_names.iter().map(|name| name.as_str()));
         }
+        wrt.write_record(&header)
+            .map_err(|err| Error::io(&summary_path, err.into()))?;
+        for gene in &results {
+            let labels = match &gene_list {
+                Some(matcher) => matcher.labels(&gene.record),
+                None => Vec::new(),
+            };
+            write_summary(&mut wrt, gene, pattern.len(), &labels, coordinates)
+                .map_err(|err| Error::io(&summary_path, err))?;
+        }
+        finish_csv(wrt, &summary_path)?;
     }
-    let mut record: Vec<String> = Vec::new();
-    for i in occurances.iter() {
-        record.push(id.clone());
-        record.push(length.clone());
-        record.push(begin.clone());
-        record.push(end.clone());
-        record.push(strand.clone());
-        record.push(i.to_string());
-        record.push(def_info.to_string());
-        writer
-            .write_record(record.clone())
-            .expect("Did not write record");
-        record.clear();
-    }
-}
-
-fn create_full_path(tdir: PathBuf, dir: String) -> PathBuf {
-    let mut s_dir = tdir.clone().into_os_string().into_string().expect("Nope");
-    s_dir.push_str("/");
-    s_dir.push_str(&dir);
-    PathBuf::from(s_dir)
-}
-
-fn get_name(pat: String, search_dir: PathBuf, seq_type: String, species: String) -> String {
-    let mut patmatch = String::new();
-    let mut mdir = String::new();
-    let mut fdir = String::from("/");
-    fdir.push_str(&species);
-    fdir.push_str(".");
-    if let Ok(files) = read_dir(search_dir.clone()) {
-        for file in files {
-            let sdir = file.unwrap().path().into_os_string().into_string().unwrap();
-            let sp = sdir.split('/').last().unwrap().split('.').next().unwrap();
-            if pat == sp {
-                patmatch.push_str(&sp);
-                let msp = sdir.split('/').last().unwrap();
-                mdir.push_str(msp);
+    if let Some(genome) = &genome {
+        let extract = |path: &PathBuf, hits: bool| -> Result<()> {
+            let mut out = Output::create(path)?;
+            for gene in &results {
+                let Some(chrom) = genome.get(&gene.record.chromosome) else {
+                    continue;
+                };
+                match hits {
+                    true => write_hit_fasta(&mut out, chrom, gene, pattern.len(), args.flank),
+                    false => write_window_fasta(&mut out, chrom, gene),
+                }
+                .map_err(|err| Error::io(path, err))?;
             }
+            out.finish().map_err(|err| Error::io(path, err))?;
+            eprintln!("Sequences written to {}", path.display());
+            Ok(())
+        };
+        if let Some(windows_path) = windows_path {
+            extract(&windows_path, false)?;
+            run.outputs.push(windows_path);
         }
-    } else {
-        println!("Path not found");
+        if let Some(hits_path) = hits_path {
+            extract(&hits_path, true)?;
+            run.outputs.push(hits_path);
+        }
+    }
+    if let Some(run_path) = run_path {
+        run.total_seconds = start.elapsed().as_secs_f64();
+        write_run_json(&run_path, &run)?;
     }
+    Ok(())
+}
 
-    if search_dir
-        .clone()
-        .into_os_string()
-        .into_string()
-        .unwrap()
-        .split('/')
-        .last()
-        .unwrap()
-        == String::from("genomes")
-    {
-        fdir.push_str(&mdir);
-        fdir.push_str(".genome_main.fna.gz");
+fn write_run_json(path: &Path, run: &RunSummary) -> Result<()> {
+    let mut out = Output::create(path)?;
+    write_run_summary(&mut out, run).map_err(|err| Error::io(path, err))?;
+    out.finish().map_err(|err| Error::io(path, err))?;
+    eprintln!("Run summary written to {}", path.display());
+    Ok(())
+}
+
+fn gene_matcher(
+    list_path: &Path,
+    gene_column: &str,
+    label_columns: &[String],
+    match_mode: &str,
+    match_field: &str,
+) -> Result<GeneMatcher> {
+    let column = Column::parse(gene_column)?;
+    let labels: Vec<Column> = label_columns
+        .iter()
+        .map(|label| Column::parse(label))
+        .collect::<Result<_>>()?;
+    let list = read_gene_list(list_path, &column, &labels)?;
+    let mode = MatchMode::parse(match_mode)?;
+    let field = Field::parse(match_field)?;
+    GeneMatcher::new(list, mode, field).map_err(|err| err.in_file(list_path))
+}
+
+/// The records of `annotation` picked by `matcher`, reporting the entries of
+/// the `list` that matched nothing.
+fn select_genes(
+    matcher: &GeneMatcher,
+    annotation: &[AnnotationRecord],
+    list: &str,
+) -> Vec<AnnotationRecord> {
+    let selection = matcher.select(annotation);
+    report_selection(matcher, annotation.len(), &selection, list);
+    selection.records
+}
+
+fn report_selection(matcher: &GeneMatcher, records: usize, selection: &Selection, list: &str) {
+    eprintln!(
+        "{} of {} annotation records are in the {}",
+        selection.records.len(),
+        records,
+        list
+    );
+    if !selection.unmatched.is_empty() {
+        eprintln!(
+            "warning: {} {} entries matched nothing:",
+            selection.unmatched.len(),
+            list
+        );
+        for &n in &selection.unmatched {
+            let entry = &matcher.entries[n];
+            eprintln!("  line {}: {}", entry.line, entry.id);
+        }
     }
+}
 
-    if search_dir
-        .clone()
-        .into_os_string()
-        .into_string()
-        .unwrap()
-        .split('/')
-        .last()
-        .unwrap()
-        == String::from("annotations")
-    {
-        fdir.push_str(&mdir);
-        fdir.push_str(".");
-        fdir.push_str(&seq_type);
-        fdir.push_str(".fna.gz");
+/// Reads the genome and annotation of `data` and searches them. The genome
+/// is returned too, unless the search went through the index.
+fn find_hits(
+    args: &SearchArgs,
+    data: &Data,
+    dialect: &Dialect,
+    gene_list: Option<&GeneMatcher>,
+    pattern: &str,
+    window: usize,
+    mismatches: usize,
+) -> Result<(Vec<GeneHits>, RunInput, Option<Genome>)> {
+    let start = Instant::now();
+    let genome_found = data.genome()?;
+    let annotation_path = data.annotation(&genome_found)?;
+    let genome_path = genome_found.path;
+    let mut annotation = load_annotation(&annotation_path, dialect)?;
+    let records = annotation.len();
+    if let Some(matcher) = gene_list {
+        annotation = select_genes(matcher, &annotation, "gene list");
     }
 
-    let mut rvec = String::new();
-    rvec.push_str(&mdir);
-    rvec.push_str(&fdir);
-    rvec
+    let read_seconds;
+    let (results, genome) = if args.index {
+        let index = load_index(&genome_path)?;
+        let headers: Vec<&str> = index.chroms.iter().map(|c| c.header.as_str()).collect();
+        check_genome(&headers, &annotation, &annotation_path)?;
+        read_seconds = start.elapsed().as_secs_f64();
+        eprintln!("Now Searching for {}", pattern);
+        (index_search(&index, &annotation, window, pattern), None)
+    } else {
+        let genome = load_genome(&genome_path)?;
+        let headers: Vec<&str> = genome
+            .chromosomes
+            .iter()
+            .map(|c| c.header.as_str())
+            .collect();
+        check_genome(&headers, &annotation, &annotation_path)?;
+        read_seconds = start.elapsed().as_secs_f64();
+        eprintln!("Now Searching for {}", pattern);
+        let results = search_genome(&genome, &annotation, window, pattern, mismatches);
+        (results, Some(genome))
+    };
+    print_elapsed(start);
+    let input = RunInput {
+        genotype: data.args.genotype.clone(),
+        genome: genome_path,
+        annotation: annotation_path,
+        records,
+        searched: annotation.len(),
+        genes_with_hits: results.iter().filter(|gene| !gene.hits.is_empty()).count(),
+        hits: results.iter().map(|gene| gene.hits.len()).sum(),
+        read_seconds,
+        search_seconds: start.elapsed().as_secs_f64() - read_seconds,
+    };
+    Ok((results, input, genome))
 }
 
-fn read_fasta<P>(filename: P) -> HashMap<String, String>
-where
-    P: AsRef<Path>,
-{
+fn load_annotation(path: &Path, dialect: &Dialect) -> Result<Vec<AnnotationRecord>> {
     let start = Instant::now();
-    let file = File::open(filename).expect("Could not open file");
-    let gz = MultiGzDecoder::new(file);
-    let buf = BufReader::new(gz);
-    let mut fasta = HashMap::new();
-    let mut curid = String::new();
-    let mut curseq = String::new();
-    for line in buf.lines() {
-        let line = line.expect("Could not read line");
-        if line.starts_with('>') {
-            if !curid.is_empty() {
-                fasta.insert(curid.clone(), curseq.clone());
-                curseq.clear();
-            }
-            // println!("{:?}", &line[..].trim());
-            curid = line[..].trim().to_string();
-        } else {
-            curseq.push_str(line.trim());
-        }
+    let annotation = read_annotation(path, dialect)?;
+    eprintln!("It took {:?} to read the annotation", start.elapsed());
+    Ok(annotation)
+}
+
+fn load_genome(path: &Path) -> Result<Genome> {
+    let start = Instant::now();
+    let genome = Genome::read(path)?;
+    eprintln!(
+        "It took {:?} to decode and read the genome",
+        start.elapsed()
+    );
+    Ok(genome)
+}
+
+fn load_index(genome_path: &Path) -> Result<GenomeIndex> {
+    let start = Instant::now();
+    let (index, origin) = GenomeIndex::load_or_build(genome_path)?;
+    report_index(&GenomeIndex::path_for(genome_path), &origin);
+    eprintln!("It took {:?} to get the index", start.elapsed());
+    Ok(index)
+}
+
+fn report_index(index_path: &Path, origin: &IndexOrigin) {
+    match origin {
+        IndexOrigin::Loaded => eprintln!("Loaded index {:?}", index_path),
+        IndexOrigin::Built => eprintln!("Built and saved index {:?}", index_path),
+        IndexOrigin::Rebuilt => eprintln!(
+            "The genome changed since {:?} was built, built and saved it again",
+            index_path
+        ),
     }
-    let duration = start.elapsed();
-    println!("It took {:?} to decode and read", duration);
-    fasta
 }
 
-fn parse_header(header: String) -> Vec<String> {
-    let sp = header.split(' ');
-    let mut definition_body: String = String::new();
-    let mut svec: Vec<String> = Vec::new();
+/// [`check_annotation`], reporting the records on sequences missing from the
+/// genome.
+fn check_genome(headers: &[&str], annotation: &[AnnotationRecord], path: &Path) -> Result<()> {
+    let missing = check_annotation(headers, annotation).map_err(|err| err.in_file(path))?;
+    report_missing(&missing);
+    Ok(())
+}
 
-    for i in sp {
-        /* finish getting the definition into one element */
-        if !i.contains("=") && !i.contains(">") {
-            definition_body.push_str(i);
-        } else {
-            svec.push(i.to_string());
-        }
+fn report_missing(missing: &[&AnnotationRecord]) {
+    if let Some(first) = missing.first() {
+        eprintln!(
+            "{} annotation records are on sequences missing from the genome, e.g. {} on {}",
+            missing.len(),
+            first.id,
+            first.chromosome
+        );
     }
-    let mut definition: String = String::new();
-    for i in &svec {
-        if i.contains("def=") {
-            definition.push_str(&i);
-        }
+}
+
+fn print_elapsed(start: Instant) {
+    print_duration(start.elapsed());
+}
+
+fn print_duration(dur: Duration) {
+    let dur_min = dur.as_secs() / 60;
+    let dur_rem = dur.as_secs() % 60;
+    eprintln!(
+        "Completed in {:?} minutes and {:?} seconds",
+        dur_min, dur_rem
+    )
+}
+
+fn enrich(args: EnrichArgs, global: GlobalArgs) -> Result<()> {
+    let config = Config::load(global.config.as_deref())?;
+    let window = args.window.or(config.defaults.window).unwrap_or(1000);
+    let mismatches = args.mismatches.or(config.defaults.mismatches).unwrap_or(0);
+    let test = Test::parse(&args.test)?;
+    let correction = Correction::parse(&args.correction)?;
+    let data = Data::resolve(args.data.clone(), &global, &config);
+    let dialect = data.dialect(
+        &config,
+        args.dialect.as_deref(),
+        args.header_regex.as_deref(),
+    )?;
+
+    let motifs: Vec<(String, String)> = match &args.pattern_fasta {
+        Some(path) => read_search_fasta(path)?
+            .into_iter()
+            .map(|(header, seq)| {
+                let name = header.trim_start_matches('>');
+                let name = name.split_whitespace().next().unwrap_or(&seq);
+                (name.to_string(), seq.clone())
+            })
+            .collect(),
+        None => args
+            .pattern
+            .iter()
+            .map(|pattern| (pattern.clone(), pattern.clone()))
+            .collect(),
+    };
+    for (_, pattern) in &motifs {
+        check_pattern(pattern, window)?;
     }
-    definition.push_str(&definition_body);
-    if let Some(ind) = svec.iter().position(|val| val.contains("def=")) {
-        svec.swap_remove(ind);
-    }
-    svec.push(definition);
-    svec
-}
-
-fn get_info(header: Vec<String>) -> Vec<String> {
-    let mut info: String = String::new();
-    let mut misc: String = String::new();
-    let mut all = Vec::new();
-
-    for idv in header {
-        let mut hsp = idv.split("=");
-        if idv.starts_with('>') {
-            info.push_str("id=");
-            info.push_str(&idv);
-            info.push_str(" ");
-        } else {
-            match hsp.next() {
-                Some("gn") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("strand") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("begin") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("end") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("loc") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("len") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("chr") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some("acc") => {
-                    info.push_str(&idv);
-                    info.push_str(" ");
-                }
-                Some(_) => {
-                    misc.push_str(&idv);
-                    misc.push_str(" ");
-                }
-                None => println!("Or this one"),
-            }
+
+    let background_name = match (&args.background, &args.background_list) {
+        (Some(name), _) => name.as_str(),
+        (None, Some(_)) => "list",
+        (None, None) => "all",
+    };
+    if (background_name == "list") != args.background_list.is_some() {
+        return Err(Error::config(
+            "--background-list goes with --background list, and only with it",
+        ));
+    }
+    if args.gene_list == Path::new("-") && args.background_list.as_deref() == Some(Path::new("-")) {
+        return Err(Error::config(
+            "--gene-list and --background-list cannot both be read from stdin",
+        ));
+    }
+    let outputs = Outputs {
+        base: format!(
+            "{}_{}_{}",
+            args.name,
+            file_name_part(&data.seq_type),
+            file_name_part(&data.species)
+        ),
+        force: args.force,
+        gzip: false,
+    };
+    let csv_path = outputs.main_path(args.output.as_deref(), "_enrichment.csv")?;
+
+    let target_list = gene_matcher(
+        &args.gene_list,
+        &args.gene_column,
+        &[],
+        &args.match_mode,
+        &args.match_field,
+    )?;
+    let genome_found = data.genome()?;
+    let annotation_path = data.annotation(&genome_found)?;
+    let annotation = load_annotation(&annotation_path, &dialect)?;
+    let genome = load_genome(&genome_found.path)?;
+    let headers: Vec<&str> = genome
+        .chromosomes
+        .iter()
+        .map(|c| c.header.as_str())
+        .collect();
+    check_genome(&headers, &annotation, &annotation_path)?;
+
+    let target = select_genes(&target_list, &annotation, "gene list");
+    if target.is_empty() {
+        return Err(Error::config(format!(
+            "no gene of {} is in the annotation",
+            args.gene_list.display()
+        )));
+    }
+    let background = match background_name {
+        "all" => Background::All,
+        "list" => {
+            let path = args.background_list.as_deref().unwrap_or(Path::new("-"));
+            let list = gene_matcher(
+                path,
+                &args.gene_column,
+                &[],
+                &args.match_mode,
+                &args.match_field,
+            )?;
+            Background::Genes(select_genes(&list, &annotation, "background list"))
         }
+        "shuffle" => Background::Shuffle {
+            samples: args.samples,
+        },
+        "markov" => Background::Markov {
+            samples: args.samples,
+            order: args.markov_order,
+        },
+        other => {
+            return Err(Error::config(format!(
+                "unknown background {:?}, expected all, list, shuffle or markov",
+                other
+            )))
+        }
+    };
+    let options = EnrichOptions {
+        window,
+        mismatches,
+        background,
+        seed: args.seed,
+        test,
+        correction,
+    };
+    eprintln!("Now Testing {} motifs", motifs.len());
+    let results = enrichment::enrich(&genome, &annotation, &target, &motifs, &options)?;
+    if let Some(result) = results.first() {
+        eprintln!(
+            "Background of {} {}",
+            result.background.genes,
+            match options.background {
+                Background::All | Background::Genes(_) => "genes",
+                _ => "sequences",
+            }
+        );
+    }
+
+    let mut wrt = Writer::from_writer(Output::create(&csv_path)?);
+    write_enrichment(&mut wrt, &results).map_err(|err| Error::io(&csv_path, err))?;
+    finish_csv(wrt, &csv_path)?;
+    if csv_path != Path::new("-") {
+        eprintln!("Enrichment written to {}", csv_path.display());
     }
-    all.push(info.trim().to_string());
-    all.push(misc.trim().to_string());
-    all
+    Ok(())
 }
@@ -0,0 +1,183 @@
+//! Matchers used for the scan over each window. Exact searches go through
+//! memchr's SIMD substring finder, searches allowing mismatches use a
+//! bit-parallel Shift-Or with one state word per allowed mismatch.
+//! All of them report every (overlapping) start offset into `text`.
+use memchr::memmem;
+
+/// Longest pattern the bit-parallel matchers can hold in one u64
+pub const WORD: usize = 64;
+
+/// Picks the fastest matcher for the pattern and number of mismatches.
+/// An empty pattern, or one longer than `text`, is found nowhere, by every
+/// matcher.
+pub fn find_all(text: &[u8], pattern: &[u8], mismatches: usize) -> Vec<usize> {
+    if mismatches == 0 {
+        memmem_find(text, pattern)
+    } else if pattern.len() <= WORD {
+        shift_or_mismatch(text, pattern, mismatches)
+    } else {
+        hamming_find(text, pattern, mismatches)
+    }
+}
+
+/// Exact matches through memchr's SIMD substring search.
+pub fn memmem_find(text: &[u8], pattern: &[u8]) -> Vec<usize> {
+    if no_room(text, pattern) {
+        return Vec::new();
+    }
+    let finder = memmem::Finder::new(pattern);
+    let mut hits: Vec<usize> = Vec::new();
+    let mut start = 0;
+    // restart one past each hit so overlapping occurrences are kept
+    while let Some(pos) = finder.find(&text[start..]) {
+        hits.push(start + pos);
+        start += pos + 1;
+        if start >= text.len() {
+            break;
+        }
+    }
+    hits
+}
+
+fn no_room(text: &[u8], pattern: &[u8]) -> bool {
+    pattern.is_empty() || pattern.len() > text.len()
+}
+
+fn masks(pattern: &[u8]) -> [u64; 256] {
+    let mut masks = [!0u64; 256];
+    for (j, &b) in pattern.iter().enumerate() {
+        masks[b as usize] &= !(1 << j);
+    }
+    masks
+}
+
+/// Shift-Or allowing up to k substitutions (k = 0 is plain Shift-Or),
+/// `state[d]` holds the prefixes that match with at most d mismatches.
+/// Patterns longer than [`WORD`] go to [`hamming_find`].
+pub fn shift_or_mismatch(text: &[u8], pattern: &[u8], k: usize) -> Vec<usize> {
+    if no_room(text, pattern) {
+        return Vec::new();
+    }
+    if pattern.len() > WORD {
+        return hamming_find(text, pattern, k);
+    }
+    let m = pattern.len();
+    if k >= m {
+        return (0..=text.len() - m).collect();
+    }
+    let masks = masks(pattern);
+    let hit = 1u64 << (m - 1);
+    let mut state = vec![!0u64; k + 1];
+    let mut hits: Vec<usize> = Vec::new();
+    for (i, &b) in text.iter().enumerate() {
+        let mask = masks[b as usize];
+        let mut prev = state[0];
+        state[0] = (state[0] << 1) | mask;
+        for cur in state[1..].iter_mut() {
+            let old = *cur;
+            *cur = ((old << 1) | mask) & (prev << 1);
+            prev = old;
+        }
+        if i + 1 >= m && state[k] & hit == 0 {
+            hits.push(i + 1 - m);
+        }
+    }
+    hits
+}
+
+/// Fallback for patterns too long for a machine word.
+pub fn hamming_find(text: &[u8], pattern: &[u8], k: usize) -> Vec<usize> {
+    if no_room(text, pattern) {
+        return Vec::new();
+    }
+    let m = pattern.len();
+    let mut hits: Vec<usize> = Vec::new();
+    for i in 0..=text.len() - m {
+        let mut diff = 0;
+        for (a, b) in text[i..i + m].iter().zip(pattern) {
+            if a != b {
+                diff += 1;
+                if diff > k {
+                    break;
+                }
+            }
+        }
+        if diff <= k {
+            hits.push(i);
+        }
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every start with at most k substitutions, the slow way
+    fn naive(text: &[u8], pattern: &[u8], k: usize) -> Vec<usize> {
+        if pattern.is_empty() || pattern.len() > text.len() {
+            return Vec::new();
+        }
+        (0..=text.len() - pattern.len())
+            .filter(|&i| {
+                let diff = text[i..i + pattern.len()]
+                    .iter()
+                    .zip(pattern)
+                    .filter(|(a, b)| a != b)
+                    .count();
+                diff <= k
+            })
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_hits_agree() {
+        let text = b"aaaaacgacgacgaaaa";
+        assert_eq!(memmem_find(text, b"aaa"), vec![0, 1, 2, 13, 14]);
+        assert_eq!(memmem_find(text, b"acgacg"), vec![4, 7]);
+        for k in 0..3 {
+            for pattern in [&b"aaa"[..], b"acgacg", b"gac"] {
+                let want = naive(text, pattern, k);
+                assert_eq!(shift_or_mismatch(text, pattern, k), want);
+                assert_eq!(hamming_find(text, pattern, k), want);
+                assert_eq!(find_all(text, pattern, k), want);
+            }
+        }
+    }
+
+    #[test]
+    fn k_of_the_pattern_length_matches_everywhere() {
+        let text = b"acgtacgt";
+        assert_eq!(
+            shift_or_mismatch(text, b"ttt", 3),
+            (0..=5).collect::<Vec<_>>()
+        );
+        assert_eq!(shift_or_mismatch(text, b"ttt", 5), naive(text, b"ttt", 5));
+        assert_eq!(hamming_find(text, b"ttt", 3), naive(text, b"ttt", 3));
+    }
+
+    #[test]
+    fn patterns_longer_than_a_word() {
+        let text: Vec<u8> = b"acgt".iter().cycle().take(300).copied().collect();
+        let mut pattern = text[10..90].to_vec();
+        pattern[5] = b'n';
+        assert_eq!(memmem_find(&text, &pattern), Vec::<usize>::new());
+        let want = naive(&text, &pattern, 1);
+        // every fourth start, as the pattern began at offset 10
+        assert_eq!(want.len(), 55);
+        assert_eq!(want[0], 2);
+        assert_eq!(shift_or_mismatch(&text, &pattern, 1), want);
+        assert_eq!(hamming_find(&text, &pattern, 1), want);
+        assert_eq!(find_all(&text, &pattern, 1), want);
+    }
+
+    #[test]
+    fn no_room_for_the_pattern() {
+        assert!(hamming_find(b"ac", b"acgt", 1).is_empty());
+        assert!(shift_or_mismatch(b"ac", b"acg", 5).is_empty());
+        assert!(memmem_find(b"ac", b"acg").is_empty());
+        assert!(shift_or_mismatch(b"acgt", b"", 1).is_empty());
+        assert!(hamming_find(b"acgt", b"", 0).is_empty());
+        assert!(memmem_find(b"acgt", b"").is_empty());
+    }
+}
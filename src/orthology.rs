@@ -0,0 +1,166 @@
+//! Orthogroup files mapping the genes of different genotypes onto each other.
+//!
+//! Either an OrthoFinder `Orthogroups.tsv`, a header starting with
+//! `Orthogroup` and then one column per proteome holding `, ` separated
+//! genes, or two columns (tab or comma separated) of gene and group, with an
+//! optional header starting with `gene`. Lines starting with `#` are skipped.
+use crate::error::{Error, Result};
+use crate::header::{AnnotationRecord, Field};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Clone, Debug, Default)]
+pub struct Orthogroups {
+    /// Orthogroup of every gene.
+    pub groups: HashMap<String, String>,
+}
+
+impl Orthogroups {
+    pub fn read<P>(filename: P) -> Result<Orthogroups>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = filename.as_ref();
+        let file = File::open(filename).map_err(|err| Error::io(filename, err))?;
+        let mut groups: HashMap<String, String> = HashMap::new();
+        let mut orthofinder = false;
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|err| Error::io(filename, err))?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let sep = if line.contains('\t') { '\t' } else { ',' };
+            let cols: Vec<&str> = line.split(sep).map(|col| col.trim()).collect();
+            if groups.is_empty() && !orthofinder {
+                if cols[0] == "Orthogroup" {
+                    orthofinder = true;
+                    continue;
+                }
+                if cols[0].eq_ignore_ascii_case("gene") {
+                    continue;
+                }
+            }
+            if orthofinder {
+                for genes in &cols[1..] {
+                    for gene in genes.split(", ").filter(|gene| !gene.is_empty()) {
+                        groups.insert(gene.trim().to_string(), cols[0].to_string());
+                    }
+                }
+            } else {
+                if cols.len() < 2 || cols[1].is_empty() {
+                    return Err(Error::parse(&line, "expected a gene and its orthogroup")
+                        .at(filename, n + 1));
+                }
+                groups.insert(cols[0].to_string(), cols[1].to_string());
+            }
+        }
+        Ok(Orthogroups { groups })
+    }
+
+    /// Orthogroup of a record, looked up by `field` first and then by its
+    /// id, gene name and locus.
+    pub fn group_of(&self, record: &AnnotationRecord, field: Field) -> Option<&str> {
+        [field, Field::Id, Field::GeneName, Field::Locus]
+            .into_iter()
+            .filter_map(|field| record.field(field))
+            .find_map(|name| self.groups.get(name))
+            .map(|group| group.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Strand;
+    use std::fs;
+
+    fn read(name: &str, text: &str) -> Result<Orthogroups> {
+        let path = std::env::temp_dir().join(format!(
+            "kbrecondo_orthology_{}_{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        let groups = Orthogroups::read(&path);
+        fs::remove_file(&path).unwrap();
+        groups
+    }
+
+    fn group<'a>(groups: &'a Orthogroups, gene: &str) -> Option<&'a str> {
+        groups.groups.get(gene).map(|group| group.as_str())
+    }
+
+    #[test]
+    fn reads_orthofinder() {
+        let groups = read(
+            "orthofinder",
+            "Orthogroup\tA17\tR108\n\
+             OG0000001\tNIN.1, NIN.2\tR108_NIN\n\
+             OG0000002\t\tR108_NSP1\n",
+        )
+        .unwrap();
+        assert_eq!(groups.groups.len(), 4);
+        assert_eq!(group(&groups, "NIN.1"), Some("OG0000001"));
+        assert_eq!(group(&groups, "NIN.2"), Some("OG0000001"));
+        assert_eq!(group(&groups, "R108_NIN"), Some("OG0000001"));
+        assert_eq!(group(&groups, "R108_NSP1"), Some("OG0000002"));
+    }
+
+    #[test]
+    fn reads_two_columns() {
+        let text = "# from the synteny run\nNIN\tOG1\nNSP1,OG2\n";
+        let groups = read("plain", text).unwrap();
+        assert_eq!(group(&groups, "NIN"), Some("OG1"));
+        assert_eq!(group(&groups, "NSP1"), Some("OG2"));
+
+        let groups = read("header", &format!("Gene\tgroup\n{}", text)).unwrap();
+        assert_eq!(groups.groups.len(), 2);
+        assert_eq!(group(&groups, "Gene"), None);
+    }
+
+    #[test]
+    fn one_column_is_a_parse_error() {
+        let err = read("short", "gene,group\nNIN,OG1\nNSP1\n").unwrap_err();
+        match err {
+            Error::Parse { path, line, .. } => {
+                assert!(path.is_some());
+                assert_eq!(line, Some(3));
+            }
+            err => panic!("expected a parse error, got {}", err),
+        }
+    }
+
+    #[test]
+    fn group_of_falls_back_to_id_name_and_locus() {
+        let groups = Orthogroups {
+            groups: HashMap::from([
+                (String::from("Medtr1g1"), String::from("by_locus")),
+                (String::from("NIN"), String::from("by_name")),
+                (String::from("NIN.1"), String::from("by_id")),
+            ]),
+        };
+        let mut record = AnnotationRecord {
+            id: String::from("NIN.1"),
+            gene_name: Some(String::from("NIN")),
+            chromosome: String::from("chr1"),
+            begin: 0,
+            end: 10,
+            strand: Strand::Plus,
+            length: None,
+            locus: Some(String::from("Medtr1g1")),
+            definition: None,
+            attributes: Vec::new(),
+        };
+        // the asked field first, then id, gene name and locus
+        assert_eq!(groups.group_of(&record, Field::Locus), Some("by_locus"));
+        assert_eq!(groups.group_of(&record, Field::GeneName), Some("by_name"));
+        record.id = String::from("other");
+        assert_eq!(groups.group_of(&record, Field::Id), Some("by_name"));
+        record.gene_name = None;
+        assert_eq!(groups.group_of(&record, Field::Id), Some("by_locus"));
+        record.locus = None;
+        assert_eq!(groups.group_of(&record, Field::Id), None);
+    }
+}
@@ -0,0 +1,735 @@
+//! Writing hits as csv, BED, GFF3 or JSON Lines, one row per occurrence, the
+//! tables comparing genotypes and motif enrichments, and the JSON summary of
+//! a run.
+use crate::enrichment::Enrichment;
+use crate::error::{Error, Result};
+use crate::extract::HitContext;
+use crate::pangenome::HitMatrix;
+use crate::search::{GeneHits, GeneSummary};
+use crate::window::Coordinates;
+use csv::Writer;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::path::{Path, PathBuf};
+
+/// Where an output goes: stdout for `-`, a gzipped file when the name ends
+/// in `.gz`, or else a plain file.
+pub enum Output {
+    Stdout(BufWriter<Stdout>),
+    File(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Output {
+    /// Creates (or truncates) the file.
+    pub fn create<P>(filename: P) -> Result<Output>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = filename.as_ref();
+        if filename == Path::new("-") {
+            return Ok(Output::Stdout(BufWriter::new(io::stdout())));
+        }
+        let file = File::create(filename).map_err(|err| Error::io(filename, err))?;
+        let file = BufWriter::new(file);
+        match filename.extension().is_some_and(|ext| ext == "gz") {
+            true => Ok(Output::Gzip(GzEncoder::new(file, Compression::default()))),
+            false => Ok(Output::File(file)),
+        }
+    }
+
+    /// Flushes everything and ends the gzip stream, dropping an [`Output`]
+    /// instead would lose write errors.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut out) => out.flush(),
+            Output::File(mut out) => out.flush(),
+            Output::Gzip(out) => out.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(out) => out.write(buf),
+            Output::File(out) => out.write(buf),
+            Output::Gzip(out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(out) => out.flush(),
+            Output::File(out) => out.flush(),
+            Output::Gzip(out) => out.flush(),
+        }
+    }
+}
+
+/// Flushes a csv writer and finishes its [`Output`].
+pub fn finish_csv(writer: Writer<Output>, filename: &Path) -> Result<()> {
+    let out = writer
+        .into_inner()
+        .map_err(|err| Error::io(filename, err.into_error()))?;
+    out.finish().map_err(|err| Error::io(filename, err))
+}
+
+pub const CSV_HEADER: [&str; 7] = [
+    "id",
+    "length",
+    "begin",
+    "end",
+    "strand",
+    "occurance.location",
+    "info",
+];
+
+/// Columns of the matched sequence and its flanks, see [`HitContext`].
+pub const CONTEXT_HEADER: [&str; 3] = ["flank.left", "match", "flank.right"];
+
+/// Writes one row per hit of `gene`, genes without hits write nothing.
+/// Positions are in `coordinates`. `contexts`, one per hit, add the columns
+/// of [`CONTEXT_HEADER`] and `labels` (e.g. from a gene list) come last,
+/// either can be empty.
+pub fn write_csv_labelled<W: Write>(
+    writer: &mut Writer<W>,
+    gene: &GeneHits,
+    labels: &[String],
+    coordinates: Coordinates,
+    contexts: &[HitContext],
+) -> io::Result<()> {
+    let record = &gene.record;
+    let length = record.length.map(|len| len.to_string()).unwrap_or_default();
+    let info = record.info();
+    for (n, hit) in gene.hits.iter().enumerate() {
+        let mut row = vec![
+            record.id.clone(),
+            length.clone(),
+            coordinates.start(record.begin - 1).to_string(),
+            coordinates.end(record.end).to_string(),
+            record.strand.to_string(),
+            coordinates.start(hit.location).to_string(),
+            info.clone(),
+        ];
+        if let Some(context) = contexts.get(n) {
+            row.push(context.left.clone());
+            row.push(context.matched.clone());
+            row.push(context.right.clone());
+        }
+        row.extend(labels.iter().cloned());
+        writer.write_record(&row)?;
+    }
+    Ok(())
+}
+
+pub const SUMMARY_HEADER: [&str; 15] = [
+    "id",
+    "gene",
+    "chromosome",
+    "begin",
+    "end",
+    "strand",
+    "window.left",
+    "window.right",
+    "window.length",
+    "hits",
+    "hits.per.kb",
+    "first.hit",
+    "closest.hit",
+    "closest.distance",
+    "info",
+];
+
+/// Writes one row for `gene` with its [`GeneSummary`], also when it has no
+/// hits. `labels` are added after the columns of [`SUMMARY_HEADER`].
+pub fn write_summary<W: Write>(
+    writer: &mut Writer<W>,
+    gene: &GeneHits,
+    pattern_len: usize,
+    labels: &[String],
+    coordinates: Coordinates,
+) -> io::Result<()> {
+    let record = &gene.record;
+    let summary = GeneSummary::new(gene, pattern_len);
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let mut row = vec![
+        record.id.clone(),
+        optional(record.gene_name.clone()),
+        record.chromosome.clone(),
+        coordinates.start(record.begin - 1).to_string(),
+        coordinates.end(record.end).to_string(),
+        record.strand.to_string(),
+        coordinates.start(gene.window.left).to_string(),
+        coordinates.end(gene.window.right).to_string(),
+        summary.window_len.to_string(),
+        summary.hits.to_string(),
+        format!("{:.3}", summary.hits_per_kb),
+        optional(
+            summary
+                .first_hit
+                .map(|hit| coordinates.start(hit).to_string()),
+        ),
+        optional(
+            summary
+                .closest_hit
+                .map(|hit| coordinates.start(hit).to_string()),
+        ),
+        optional(summary.closest_distance.map(|d| d.to_string())),
+        record.info(),
+    ];
+    row.extend(labels.iter().cloned());
+    writer.write_record(&row)?;
+    Ok(())
+}
+
+/// Writes a gene × genotype table of hit counts, `NA` where a genotype does
+/// not have the gene.
+pub fn write_matrix<W: Write>(writer: &mut Writer<W>, matrix: &HitMatrix) -> io::Result<()> {
+    let mut header = vec![matrix.row_name.as_str()];
+    header.extend(matrix.genotypes.iter().map(|g| g.as_str()));
+    writer.write_record(&header)?;
+    for (gene, counts) in &matrix.genes {
+        let mut row = vec![gene.clone()];
+        row.extend(counts.iter().map(|count| match count {
+            Some(count) => count.to_string(),
+            None => String::from("NA"),
+        }));
+        writer.write_record(&row)?;
+    }
+    Ok(())
+}
+
+pub const PRESENCE_HEADER: [&str; 6] = [
+    "genotypes",
+    "with.hits",
+    "without.hits",
+    "present.in",
+    "absent.in",
+    "missing.in",
+];
+
+/// Writes one row per gene saying in which genotypes it has hits, has none,
+/// or is not annotated. Genotypes are separated by `;`.
+pub fn write_presence<W: Write>(writer: &mut Writer<W>, matrix: &HitMatrix) -> io::Result<()> {
+    let mut header = vec![matrix.row_name.as_str()];
+    header.extend(PRESENCE_HEADER);
+    writer.write_record(&header)?;
+    for gene in matrix.genes.keys() {
+        let presence = matrix.presence(gene);
+        writer.write_record([
+            gene.as_str(),
+            &(presence.present.len() + presence.absent.len()).to_string(),
+            &presence.present.len().to_string(),
+            &presence.absent.len().to_string(),
+            &presence.present.join(";"),
+            &presence.absent.join(";"),
+            &presence.missing.join(";"),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Formats the hits can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One row per hit with the columns of [`CSV_HEADER`].
+    Csv,
+    /// BED6, 0 based half open.
+    Bed,
+    /// GFF3, 1 based inclusive.
+    Gff3,
+    /// One JSON object per hit and line, see [`write_jsonl`].
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// `csv`, `bed`, `gff3` or `jsonl`.
+    pub fn parse(format: &str) -> Result<OutputFormat> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "bed" | "bed6" => Ok(OutputFormat::Bed),
+            "gff" | "gff3" => Ok(OutputFormat::Gff3),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            _ => Err(Error::config(format!(
+                "unknown output format {:?}, expected csv, bed, gff3 or jsonl",
+                format
+            ))),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Bed => "bed",
+            OutputFormat::Gff3 => "gff3",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+// 1000 for an exact match, less for every mismatch
+fn score(mismatches: usize, pattern_len: usize) -> usize {
+    1000 - (1000 * mismatches.min(pattern_len)) / pattern_len.max(1)
+}
+
+/// Writes one BED6 line per hit of `gene`, named after the gene with the
+/// score falling from 1000 with every mismatch.
+pub fn write_bed<W: Write>(out: &mut W, gene: &GeneHits, pattern_len: usize) -> io::Result<()> {
+    let record = &gene.record;
+    let name = record.gene_name.as_deref().unwrap_or(&record.id);
+    for hit in &gene.hits {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            record.chromosome,
+            hit.location,
+            hit.location + pattern_len,
+            name,
+            score(hit.mismatches, pattern_len),
+            record.strand
+        )?;
+    }
+    Ok(())
+}
+
+pub const GFF3_HEADER: &str = "##gff-version 3";
+
+// percent encodes the characters GFF3 reserves in attribute values
+fn gff3_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            ';' | '=' | '&' | ',' | '%' | '\t' | '\n' | '\r' => {
+                escaped.push_str(&format!("%{:02X}", c as u32))
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes one GFF3 `sequence_motif` line per hit of `gene`, with the gene
+/// and `query` ids and `labels` (name, value) as attributes. `count` numbers
+/// the hits across calls for their `ID`s.
+pub fn write_gff3<W: Write>(
+    out: &mut W,
+    gene: &GeneHits,
+    pattern_len: usize,
+    query: &str,
+    labels: &[(&str, &str)],
+    count: &mut usize,
+) -> io::Result<()> {
+    let record = &gene.record;
+    for hit in &gene.hits {
+        *count += 1;
+        let mut attributes = vec![
+            format!("ID=hit{}", count),
+            format!("Name={}", gff3_escape(query)),
+            format!("gene={}", gff3_escape(&record.id)),
+            format!("query={}", gff3_escape(query)),
+            format!("mismatches={}", hit.mismatches),
+        ];
+        if let Some(gene_name) = &record.gene_name {
+            attributes.push(format!("gene_name={}", gff3_escape(gene_name)));
+        }
+        for (name, value) in labels {
+            if !value.is_empty() {
+                attributes.push(format!("{}={}", gff3_escape(name), gff3_escape(value)));
+            }
+        }
+        writeln!(
+            out,
+            "{}\tkbrecondo\tsequence_motif\t{}\t{}\t{}\t{}\t.\t{}",
+            record.chromosome,
+            hit.location + 1,
+            hit.location + pattern_len,
+            score(hit.mismatches, pattern_len),
+            record.strand,
+            attributes.join(";")
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonHit<'a> {
+    query: &'a str,
+    id: &'a str,
+    gene: Option<&'a str>,
+    locus: Option<&'a str>,
+    chromosome: &'a str,
+    begin: usize,
+    end: usize,
+    strand: &'a str,
+    length: Option<usize>,
+    window_left: usize,
+    window_right: usize,
+    location: usize,
+    mismatches: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flank_left: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flank_right: Option<&'a str>,
+    definition: Option<&'a str>,
+    attributes: BTreeMap<&'a str, &'a str>,
+    labels: BTreeMap<&'a str, &'a str>,
+}
+
+/// Writes one JSON object per hit of `gene` and line. Besides the columns
+/// of [`CSV_HEADER`] it has the query, the window and the fields of the
+/// annotation header that `info` packs together, `labels` are (name, value).
+/// `contexts` add the matched sequence and its flanks when not empty.
+pub fn write_jsonl<W: Write>(
+    out: &mut W,
+    gene: &GeneHits,
+    query: &str,
+    labels: &[(&str, &str)],
+    coordinates: Coordinates,
+    contexts: &[HitContext],
+) -> io::Result<()> {
+    let record = &gene.record;
+    for (n, hit) in gene.hits.iter().enumerate() {
+        let context = contexts.get(n);
+        let row = JsonHit {
+            query,
+            id: &record.id,
+            gene: record.gene_name.as_deref(),
+            locus: record.locus.as_deref(),
+            chromosome: &record.chromosome,
+            begin: coordinates.start(record.begin - 1),
+            end: coordinates.end(record.end),
+            strand: record.strand.as_str(),
+            length: record.length,
+            window_left: coordinates.start(gene.window.left),
+            window_right: coordinates.end(gene.window.right),
+            location: coordinates.start(hit.location),
+            mismatches: hit.mismatches,
+            flank_left: context.map(|context| context.left.as_str()),
+            matched: context.map(|context| context.matched.as_str()),
+            flank_right: context.map(|context| context.right.as_str()),
+            definition: record.definition.as_deref(),
+            attributes: record
+                .attributes
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect(),
+            labels: labels.iter().cloned().collect(),
+        };
+        serde_json::to_writer(&mut *out, &row)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// What was searched for and how, for [`RunSummary`].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunParameters {
+    pub query: String,
+    pub pattern: String,
+    pub species: String,
+    pub seq_type: String,
+    pub window: usize,
+    pub mismatches: usize,
+    /// `0-based` or `1-based`.
+    pub coordinates: String,
+    pub index: bool,
+    pub format: String,
+    pub gene_list: Option<PathBuf>,
+    pub match_mode: Option<String>,
+    pub match_field: Option<String>,
+}
+
+/// The files of one genotype and what was found in them.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunInput {
+    pub genotype: Option<String>,
+    pub genome: PathBuf,
+    pub annotation: PathBuf,
+    /// Records in the annotation.
+    pub records: usize,
+    /// Records searched, less than `records` with a gene list.
+    pub searched: usize,
+    pub genes_with_hits: usize,
+    pub hits: usize,
+    /// Reading the genome and annotation.
+    pub read_seconds: f64,
+    pub search_seconds: f64,
+}
+
+/// Parameters, input files, counts and timings of a search, written as JSON
+/// next to the hits.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunSummary {
+    pub version: String,
+    pub parameters: RunParameters,
+    pub inputs: Vec<RunInput>,
+    pub outputs: Vec<PathBuf>,
+    /// Totals over `inputs`.
+    pub searched: usize,
+    pub genes_with_hits: usize,
+    pub hits: usize,
+    pub total_seconds: f64,
+}
+
+impl RunSummary {
+    pub fn new(parameters: RunParameters) -> RunSummary {
+        RunSummary {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            parameters,
+            ..RunSummary::default()
+        }
+    }
+
+    pub fn add(&mut self, input: RunInput) {
+        self.searched += input.searched;
+        self.genes_with_hits += input.genes_with_hits;
+        self.hits += input.hits;
+        self.inputs.push(input);
+    }
+}
+
+/// Writes `summary` as pretty printed JSON.
+pub fn write_run_summary<W: Write>(out: &mut W, summary: &RunSummary) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, summary)?;
+    writeln!(out)
+}
+
+pub const ENRICHMENT_HEADER: [&str; 16] = [
+    "motif",
+    "pattern",
+    "target.genes",
+    "target.with.hits",
+    "target.hits",
+    "target.positions",
+    "background.genes",
+    "background.with.hits",
+    "background.hits",
+    "background.positions",
+    "expected.with.hits",
+    "expected.hits",
+    "fold.enrichment",
+    "log2.fold",
+    "p.value",
+    "p.adjusted",
+];
+
+/// Writes one row per motif, the fold enrichment is `NA` when no hits are
+/// expected.
+pub fn write_enrichment<W: Write>(
+    writer: &mut Writer<W>,
+    results: &[Enrichment],
+) -> io::Result<()> {
+    writer.write_record(ENRICHMENT_HEADER)?;
+    for result in results {
+        let (fold, log2) = match result.fold {
+            Some(fold) => (format!("{:.3}", fold), format!("{:.3}", fold.log2())),
+            None => (String::from("NA"), String::from("NA")),
+        };
+        writer.write_record([
+            result.motif.clone(),
+            result.pattern.clone(),
+            result.target.genes.to_string(),
+            result.target.genes_with_hits.to_string(),
+            result.target.hits.to_string(),
+            result.target.positions.to_string(),
+            result.background.genes.to_string(),
+            result.background.genes_with_hits.to_string(),
+            result.background.hits.to_string(),
+            result.background.positions.to_string(),
+            format!("{:.3}", result.expected_genes),
+            format!("{:.3}", result.expected_hits),
+            fold,
+            log2,
+            format!("{:.4e}", result.p_value),
+            format!("{:.4e}", result.p_adjusted),
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{AnnotationRecord, Strand};
+    use crate::search::Hit;
+    use crate::window::Window;
+    use serde_json::Value;
+
+    fn gene(id: &str, gene_name: Option<&str>, hits: &[(usize, usize)]) -> GeneHits {
+        GeneHits {
+            record: AnnotationRecord {
+                id: id.to_string(),
+                gene_name: gene_name.map(String::from),
+                chromosome: String::from("chr1"),
+                begin: 101,
+                end: 200,
+                strand: Strand::Minus,
+                length: Some(100),
+                locus: None,
+                definition: None,
+                attributes: Vec::new(),
+            },
+            window: Window {
+                left: 100,
+                right: 1200,
+            },
+            hits: hits
+                .iter()
+                .map(|&(location, mismatches)| Hit {
+                    location,
+                    mismatches,
+                })
+                .collect(),
+        }
+    }
+
+    fn lines(out: Vec<u8>) -> Vec<String> {
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn bed_is_zero_based_half_open() {
+        let mut out: Vec<u8> = Vec::new();
+        write_bed(
+            &mut out,
+            &gene("NIN.1", Some("NIN"), &[(500, 0), (0, 2)]),
+            8,
+        )
+        .unwrap();
+        assert_eq!(
+            lines(out),
+            ["chr1\t500\t508\tNIN\t1000\t-", "chr1\t0\t8\tNIN\t750\t-"]
+        );
+        let mut out: Vec<u8> = Vec::new();
+        write_bed(&mut out, &gene("NIN.1", None, &[(500, 0)]), 8).unwrap();
+        assert_eq!(lines(out), ["chr1\t500\t508\tNIN.1\t1000\t-"]);
+    }
+
+    #[test]
+    fn score_drops_with_every_mismatch() {
+        assert_eq!(score(0, 10), 1000);
+        assert_eq!(score(1, 10), 900);
+        assert_eq!(score(3, 10), 700);
+        assert_eq!(score(10, 10), 0);
+        assert_eq!(score(12, 10), 0);
+        assert_eq!(score(0, 0), 1000);
+    }
+
+    #[test]
+    fn gff3_escapes_reserved_characters() {
+        assert_eq!(gff3_escape("NIN"), "NIN");
+        assert_eq!(gff3_escape("a;b=c,d"), "a%3Bb%3Dc%2Cd");
+        assert_eq!(gff3_escape("50%"), "50%25");
+    }
+
+    #[test]
+    fn gff3_is_one_based_and_numbers_hits_across_genes() {
+        let mut out: Vec<u8> = Vec::new();
+        let mut count = 0;
+        let labels = [("group", "nodulation;early"), ("note", "")];
+        let nin = gene("NIN.1", Some("NIN"), &[(500, 0), (600, 1)]);
+        write_gff3(&mut out, &nin, 8, "NRE", &labels, &mut count).unwrap();
+        let nsp1 = gene("NSP1.1", None, &[(0, 0)]);
+        write_gff3(&mut out, &nsp1, 8, "NRE", &labels, &mut count).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(
+            lines(out),
+            [
+                "chr1\tkbrecondo\tsequence_motif\t501\t508\t1000\t-\t.\t\
+                 ID=hit1;Name=NRE;gene=NIN.1;query=NRE;mismatches=0;gene_name=NIN;\
+                 group=nodulation%3Bearly",
+                "chr1\tkbrecondo\tsequence_motif\t601\t608\t875\t-\t.\t\
+                 ID=hit2;Name=NRE;gene=NIN.1;query=NRE;mismatches=1;gene_name=NIN;\
+                 group=nodulation%3Bearly",
+                "chr1\tkbrecondo\tsequence_motif\t1\t8\t1000\t-\t.\t\
+                 ID=hit3;Name=NRE;gene=NSP1.1;query=NRE;mismatches=0;\
+                 group=nodulation%3Bearly",
+            ]
+        );
+    }
+
+    fn jsonl(gene: &GeneHits, coordinates: Coordinates, contexts: &[HitContext]) -> Value {
+        let mut out: Vec<u8> = Vec::new();
+        write_jsonl(
+            &mut out,
+            gene,
+            "NRE",
+            &[("group", "early")],
+            coordinates,
+            contexts,
+        )
+        .unwrap();
+        let lines = lines(out);
+        assert_eq!(lines.len(), 1);
+        serde_json::from_str(&lines[0]).unwrap()
+    }
+
+    #[test]
+    fn jsonl_round_trips() {
+        let mut nin = gene("NIN.1", Some("NIN"), &[(500, 1)]);
+        nin.record.attributes = vec![(String::from("note"), String::from("a, b"))];
+
+        let row = jsonl(&nin, Coordinates::OneBased, &[]);
+        assert_eq!(row["query"], "NRE");
+        assert_eq!(row["id"], "NIN.1");
+        assert_eq!(row["gene"], "NIN");
+        assert_eq!(row["locus"], Value::Null);
+        assert_eq!(row["strand"], "-");
+        assert_eq!(row["length"], 100);
+        assert_eq!(row["mismatches"], 1);
+        assert_eq!(row["attributes"]["note"], "a, b");
+        assert_eq!(row["labels"]["group"], "early");
+        assert_eq!(
+            [&row["begin"], &row["end"], &row["location"]],
+            [101, 200, 501]
+        );
+        assert_eq!([&row["window_left"], &row["window_right"]], [101, 1200]);
+        let keys = row.as_object().unwrap();
+        for key in ["flank_left", "matched", "flank_right"] {
+            assert!(!keys.contains_key(key), "{}", key);
+        }
+
+        let context = HitContext {
+            left: String::from("ac"),
+            matched: String::from("GATTACA"),
+            right: String::from("gt"),
+            begin: 498,
+            end: 509,
+        };
+        let row = jsonl(&nin, Coordinates::ZeroBased, &[context]);
+        assert_eq!(
+            [&row["begin"], &row["end"], &row["location"]],
+            [100, 200, 500]
+        );
+        assert_eq!([&row["window_left"], &row["window_right"]], [100, 1200]);
+        assert_eq!(row["flank_left"], "ac");
+        assert_eq!(row["matched"], "GATTACA");
+        assert_eq!(row["flank_right"], "gt");
+    }
+
+    #[test]
+    fn run_summary_adds_up_the_inputs() {
+        let mut run = RunSummary::new(RunParameters::default());
+        for (hits, genes_with_hits, searched) in [(5, 2, 10), (3, 3, 20)] {
+            run.add(RunInput {
+                searched,
+                genes_with_hits,
+                hits,
+                ..RunInput::default()
+            });
+        }
+        assert_eq!(run.inputs.len(), 2);
+        assert_eq!((run.hits, run.genes_with_hits, run.searched), (8, 5, 30));
+    }
+}
@@ -0,0 +1,441 @@
+//! Comparing the hits of one search across genotypes, genes are lined up by
+//! a field of their annotation record or by their orthogroup.
+//!
+//! [`columns`] picks the genomes and annotations of an [`Inventory`] to
+//! compare and [`run`] searches them all into a [`HitMatrix`].
+use crate::dialect::Dialect;
+use crate::error::{Error, Result};
+use crate::fasta::{read_annotation, Genome};
+use crate::fmindex::{GenomeIndex, IndexOrigin};
+use crate::genelist::{GeneMatcher, Selection};
+use crate::header::{AnnotationRecord, Field};
+use crate::inventory::Inventory;
+use crate::orthology::Orthogroups;
+use crate::output::RunInput;
+use crate::search::{check_annotation, index_search, search_genome, GeneHits};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// How genes are named in a [`HitMatrix`].
+#[derive(Clone, Debug)]
+pub struct GeneKey {
+    /// Records without this field are named by their id.
+    pub field: Field,
+    /// Genes in an orthogroup are counted as the orthogroup.
+    pub orthogroups: Option<Orthogroups>,
+}
+
+impl GeneKey {
+    pub fn key(&self, record: &AnnotationRecord) -> String {
+        if let Some(groups) = &self.orthogroups {
+            if let Some(group) = groups.group_of(record, self.field) {
+                return group.to_string();
+            }
+        }
+        record.field(self.field).unwrap_or(&record.id).to_string()
+    }
+
+    /// What the rows are, `gene` or `orthogroup`.
+    pub fn row_name(&self) -> &'static str {
+        match self.orthogroups {
+            Some(_) => "orthogroup",
+            None => "gene",
+        }
+    }
+}
+
+/// Hit counts of every gene in every genotype.
+#[derive(Clone, Debug, Default)]
+pub struct HitMatrix {
+    /// `gene` or `orthogroup`.
+    pub row_name: String,
+    pub genotypes: Vec<String>,
+    /// Per gene the number of distinct hits in each genotype, `None` where
+    /// the genotype has no such gene.
+    pub genes: BTreeMap<String, Vec<Option<usize>>>,
+}
+
+/// How one gene is spread over the genotypes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Presence {
+    /// Genotypes with at least one hit.
+    pub present: Vec<String>,
+    /// Genotypes with the gene but without a hit.
+    pub absent: Vec<String>,
+    /// Genotypes without the gene.
+    pub missing: Vec<String>,
+}
+
+impl HitMatrix {
+    pub fn new(row_name: &str, genotypes: Vec<String>) -> HitMatrix {
+        HitMatrix {
+            row_name: row_name.to_string(),
+            genotypes,
+            genes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the results of `genotype`. Records with the same key (e.g. the
+    /// transcripts of a gene, or the genes of an orthogroup) are one row, an
+    /// occurrence in several of their windows is counted once.
+    pub fn add(&mut self, genotype: &str, results: &[GeneHits], key: &GeneKey) {
+        let column = match self.genotypes.iter().position(|g| g == genotype) {
+            Some(column) => column,
+            None => {
+                self.genotypes.push(genotype.to_string());
+                for counts in self.genes.values_mut() {
+                    counts.push(None);
+                }
+                self.genotypes.len() - 1
+            }
+        };
+        let mut hits: BTreeMap<String, BTreeSet<(&str, usize)>> = BTreeMap::new();
+        for gene in results {
+            let record = &gene.record;
+            let locations = hits.entry(key.key(record)).or_default();
+            for hit in &gene.hits {
+                locations.insert((&record.chromosome, hit.location));
+            }
+        }
+        let width = self.genotypes.len();
+        for (key, locations) in hits {
+            let counts = self.genes.entry(key).or_insert_with(|| vec![None; width]);
+            counts[column] = Some(locations.len());
+        }
+    }
+
+    pub fn presence(&self, gene: &str) -> Presence {
+        let mut presence = Presence::default();
+        let Some(counts) = self.genes.get(gene) else {
+            return presence;
+        };
+        for (genotype, count) in self.genotypes.iter().zip(counts) {
+            match count {
+                Some(0) => presence.absent.push(genotype.clone()),
+                Some(_) => presence.present.push(genotype.clone()),
+                None => presence.missing.push(genotype.clone()),
+            }
+        }
+        presence
+    }
+}
+
+/// One genome and annotation of a [`run`], a column of the [`HitMatrix`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    /// The genotype, followed by the assembly and the annotation version
+    /// where the genotype has more than one.
+    pub name: String,
+    pub genotype: String,
+    pub genome: PathBuf,
+    pub annotation: PathBuf,
+}
+
+/// A column for every annotation of `seq_type` in `inventory` that has a
+/// genome, in inventory order.
+pub fn columns(inventory: &Inventory, seq_type: &str) -> Result<Vec<Column>> {
+    // (genotype, assembly, annotation version) of every column
+    let mut keys: Vec<(&str, &str, &str)> = Vec::new();
+    let mut columns: Vec<Column> = Vec::new();
+    for ann in &inventory.annotations {
+        let Some(annotation) = ann.seq_types.get(seq_type) else {
+            continue;
+        };
+        let Some(genome) = inventory
+            .genomes
+            .iter()
+            .find(|genome| genome.key == ann.key)
+        else {
+            continue;
+        };
+        let (species, genotype, assembly) = &ann.key;
+        if genome.paths.len() > 1 {
+            return Err(Error::config(format!(
+                "{} {} {} has {} genome files, make the genome template more specific",
+                species,
+                genotype,
+                assembly,
+                genome.paths.len()
+            )));
+        }
+        keys.push((genotype, assembly, &ann.annotation));
+        columns.push(Column {
+            name: genotype.clone(),
+            genotype: genotype.clone(),
+            genome: genome.paths[0].clone(),
+            annotation: annotation.clone(),
+        });
+    }
+    for (column, &(genotype, assembly, annotation)) in columns.iter_mut().zip(&keys) {
+        if keys.iter().filter(|other| other.0 == genotype).count() > 1 {
+            column.name = format!("{}.{}", column.name, assembly);
+        }
+        let same_assembly = keys
+            .iter()
+            .filter(|other| other.0 == genotype && other.1 == assembly)
+            .count();
+        if same_assembly > 1 {
+            column.name = format!("{}.{}", column.name, annotation);
+        }
+    }
+    Ok(columns)
+}
+
+/// Settings of [`run`].
+pub struct PangenomeSearch<'a> {
+    pub dialect: Dialect,
+    /// Only the genes of the list are searched.
+    pub gene_list: Option<&'a GeneMatcher>,
+    pub pattern: String,
+    /// Bases searched on each side of a gene.
+    pub window: usize,
+    pub mismatches: usize,
+    /// Search through the FM-index of each genome, see
+    /// [`GenomeIndex::load_or_build`].
+    pub index: bool,
+    pub key: GeneKey,
+}
+
+/// What [`run`] is doing, for the caller to report.
+pub enum Progress<'a> {
+    /// A column is about to be read and searched.
+    Started(&'a Column),
+    /// The gene list picked these of `records` annotation records.
+    Selected {
+        records: usize,
+        selection: &'a Selection,
+    },
+    /// How the FM-index of `genome` was got.
+    Index {
+        genome: &'a Path,
+        origin: IndexOrigin,
+    },
+    /// Annotation records on sequences missing from the genome, they are
+    /// searched nowhere.
+    Missing(&'a [&'a AnnotationRecord]),
+    /// The column was searched.
+    Finished(&'a RunInput),
+}
+
+/// Searches every column, telling `progress` about each step.
+pub fn run<F: FnMut(Progress)>(
+    columns: &[Column],
+    search: &PangenomeSearch,
+    mut progress: F,
+) -> Result<(HitMatrix, Vec<RunInput>)> {
+    let names = columns.iter().map(|column| column.name.clone()).collect();
+    let mut matrix = HitMatrix::new(search.key.row_name(), names);
+    let mut inputs: Vec<RunInput> = Vec::new();
+    for column in columns {
+        progress(Progress::Started(column));
+        let (results, input) = search_column(column, search, &mut progress)?;
+        progress(Progress::Finished(&input));
+        matrix.add(&column.name, &results, &search.key);
+        inputs.push(input);
+    }
+    Ok((matrix, inputs))
+}
+
+fn search_column<F: FnMut(Progress)>(
+    column: &Column,
+    search: &PangenomeSearch,
+    progress: &mut F,
+) -> Result<(Vec<GeneHits>, RunInput)> {
+    let start = Instant::now();
+    let mut annotation = read_annotation(&column.annotation, &search.dialect)?;
+    let records = annotation.len();
+    if let Some(matcher) = search.gene_list {
+        let selection = matcher.select(&annotation);
+        progress(Progress::Selected {
+            records,
+            selection: &selection,
+        });
+        annotation = selection.records;
+    }
+    let mut check = |headers: Vec<&str>| -> Result<()> {
+        let missing = check_annotation(&headers, &annotation)
+            .map_err(|err| err.in_file(&column.annotation))?;
+        if !missing.is_empty() {
+            progress(Progress::Missing(&missing));
+        }
+        Ok(())
+    };
+    let read_seconds;
+    let results = if search.index {
+        let (index, origin) = GenomeIndex::load_or_build(&column.genome)?;
+        check(index.chroms.iter().map(|c| c.header.as_str()).collect())?;
+        progress(Progress::Index {
+            genome: &column.genome,
+            origin,
+        });
+        read_seconds = start.elapsed().as_secs_f64();
+        index_search(&index, &annotation, search.window, &search.pattern)
+    } else {
+        let genome = Genome::read(&column.genome)?;
+        check(
+            genome
+                .chromosomes
+                .iter()
+                .map(|c| c.header.as_str())
+                .collect(),
+        )?;
+        read_seconds = start.elapsed().as_secs_f64();
+        search_genome(
+            &genome,
+            &annotation,
+            search.window,
+            &search.pattern,
+            search.mismatches,
+        )
+    };
+    let input = RunInput {
+        genotype: Some(column.genotype.clone()),
+        genome: column.genome.clone(),
+        annotation: column.annotation.clone(),
+        records,
+        searched: annotation.len(),
+        genes_with_hits: results.iter().filter(|gene| !gene.hits.is_empty()).count(),
+        hits: results.iter().map(|gene| gene.hits.len()).sum(),
+        read_seconds,
+        search_seconds: start.elapsed().as_secs_f64() - read_seconds,
+    };
+    Ok((results, input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Strand;
+    use crate::inventory::{AnnotationEntry, GenomeEntry};
+    use crate::search::Hit;
+    use crate::window::Window;
+
+    fn gene(id: &str, name: &str, locations: &[usize]) -> GeneHits {
+        GeneHits {
+            record: AnnotationRecord {
+                id: id.to_string(),
+                gene_name: Some(name.to_string()),
+                chromosome: String::from("chr1"),
+                begin: 100,
+                end: 200,
+                strand: Strand::Plus,
+                length: None,
+                locus: None,
+                definition: None,
+                attributes: Vec::new(),
+            },
+            window: Window {
+                left: 0,
+                right: 300,
+            },
+            hits: locations
+                .iter()
+                .map(|&location| Hit {
+                    location,
+                    mismatches: 0,
+                })
+                .collect(),
+        }
+    }
+
+    fn by_name() -> GeneKey {
+        GeneKey {
+            field: Field::GeneName,
+            orthogroups: None,
+        }
+    }
+
+    #[test]
+    fn add_counts_a_hit_of_several_records_once() {
+        let key = by_name();
+        let mut matrix = HitMatrix::new(key.row_name(), vec![String::from("A17")]);
+        // two transcripts of NIN share the hit at 50
+        let results = [
+            gene("NIN.1", "NIN", &[50, 60]),
+            gene("NIN.2", "NIN", &[50]),
+            gene("NSP1.1", "NSP1", &[]),
+        ];
+        matrix.add("A17", &results, &key);
+        assert_eq!(matrix.genes["NIN"], vec![Some(2)]);
+        assert_eq!(matrix.genes["NSP1"], vec![Some(0)]);
+
+        // a genotype the matrix was not made with gets a new column
+        matrix.add("R108", &[gene("NIN.1", "NIN", &[70])], &key);
+        assert_eq!(matrix.genotypes, ["A17", "R108"]);
+        assert_eq!(matrix.genes["NIN"], vec![Some(2), Some(1)]);
+        assert_eq!(matrix.genes["NSP1"], vec![Some(0), None]);
+    }
+
+    #[test]
+    fn presence_sorts_the_genotypes() {
+        let key = by_name();
+        let genotypes = ["A17", "R108", "HM340"].map(String::from).to_vec();
+        let mut matrix = HitMatrix::new(key.row_name(), genotypes);
+        matrix.add("A17", &[gene("NIN.1", "NIN", &[50])], &key);
+        matrix.add("R108", &[gene("NIN.1", "NIN", &[])], &key);
+        matrix.add("HM340", &[gene("NSP1.1", "NSP1", &[10])], &key);
+        assert_eq!(
+            matrix.presence("NIN"),
+            Presence {
+                present: vec![String::from("A17")],
+                absent: vec![String::from("R108")],
+                missing: vec![String::from("HM340")],
+            }
+        );
+        assert_eq!(matrix.presence("NFP"), Presence::default());
+    }
+
+    #[test]
+    fn columns_are_named_apart_only_where_needed() {
+        let key = |genotype: &str, assembly: &str| {
+            (
+                String::from("Mtruncatula"),
+                genotype.to_string(),
+                assembly.to_string(),
+            )
+        };
+        let genome = |genotype: &str, assembly: &str| GenomeEntry {
+            key: key(genotype, assembly),
+            paths: vec![PathBuf::from(format!("{}.{}.fa.gz", genotype, assembly))],
+        };
+        let annotation = |genotype: &str, assembly: &str, annotation: &str| AnnotationEntry {
+            key: key(genotype, assembly),
+            annotation: annotation.to_string(),
+            seq_types: BTreeMap::from([(
+                String::from("cds"),
+                PathBuf::from(format!(
+                    "{}.{}.{}.cds.fa.gz",
+                    genotype, assembly, annotation
+                )),
+            )]),
+        };
+        let inventory = Inventory {
+            genomes: vec![
+                genome("A17", "gnm4"),
+                genome("A17", "gnm5"),
+                genome("R108", "gnm1"),
+            ],
+            annotations: vec![
+                annotation("A17", "gnm4", "ann1"),
+                annotation("A17", "gnm5", "ann1"),
+                annotation("A17", "gnm5", "ann2"),
+                annotation("R108", "gnm1", "ann1"),
+                // no genome
+                annotation("HM340", "gnm1", "ann1"),
+            ],
+        };
+        let columns = columns(&inventory, "cds").unwrap();
+        let names: Vec<&str> = columns.iter().map(|column| column.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["A17.gnm4", "A17.gnm5.ann1", "A17.gnm5.ann2", "R108"]
+        );
+        assert_eq!(columns[3].genome, PathBuf::from("R108.gnm1.fa.gz"));
+        assert_eq!(
+            columns[3].annotation,
+            PathBuf::from("R108.gnm1.ann1.cds.fa.gz")
+        );
+        assert!(super::columns(&inventory, "protein").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,400 @@
+//! Locating the genome and annotation files in the genome and annotation
+//! directories.
+//!
+//! Files are found with a template, a path relative to the data directory
+//! with placeholders, e.g. the legumeinfo layout of
+//! <https://data.legumeinfo.org/Medicago/truncatula/>
+//!
+//! ```text
+//! {genotype}.{assembly}.*/{species}.{dir}.genome_main.fna.gz
+//! ```
+//!
+//! The placeholders are `{species}`, `{genotype}`, `{assembly}` (e.g.
+//! `gnm5`), `{annotation}` (e.g. `ann1_6`), `{seq_type}` and `{dir}`, the
+//! name of the directory the file or directory is in. A placeholder without
+//! a value matches any text without a dot, and has to match the same text
+//! everywhere it is used. `*` matches any text. A template without a `/` is
+//! looked for in the directories whose first dot separated part is the
+//! genotype.
+use crate::error::{Error, Result};
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+pub const PLACEHOLDERS: [&str; 6] = [
+    "species",
+    "genotype",
+    "assembly",
+    "annotation",
+    "seq_type",
+    "dir",
+];
+
+/// Values filled into a template, `None` matches anything.
+#[derive(Clone, Debug, Default)]
+pub struct Names {
+    pub species: Option<String>,
+    pub genotype: Option<String>,
+    pub assembly: Option<String>,
+    pub annotation: Option<String>,
+    pub seq_type: Option<String>,
+}
+
+impl Names {
+    /// Value of a placeholder, `None` if it is not set.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        match field {
+            "species" => self.species.as_deref(),
+            "genotype" => self.genotype.as_deref(),
+            "assembly" => self.assembly.as_deref(),
+            "annotation" => self.annotation.as_deref(),
+            "seq_type" => self.seq_type.as_deref(),
+            _ => None,
+        }
+    }
+
+    // the values that are set, for error messages
+    fn describe(&self) -> String {
+        PLACEHOLDERS
+            .iter()
+            .filter_map(|field| self.get(field).map(|value| format!("{}={}", field, value)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// A file found with a template.
+#[derive(Clone, Debug)]
+pub struct TemplateMatch {
+    pub path: PathBuf,
+    /// What the placeholders without a value matched.
+    pub fields: Vec<(String, String)>,
+}
+
+impl TemplateMatch {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+enum Token {
+    Literal(String),
+    Field(String),
+    Star,
+}
+
+fn tokenize(
+    component: &str,
+    template: &str,
+    names: &Names,
+    dir: Option<&str>,
+) -> Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut literal = String::new();
+    let mut rest = component;
+    while let Some(c) = rest.chars().next() {
+        if c == '{' {
+            let close = rest.find('}').ok_or_else(|| {
+                Error::config(format!("unclosed {{ in file name template {:?}", template))
+            })?;
+            let field = &rest[1..close];
+            let value = match field {
+                "dir" => Some(dir.ok_or_else(|| {
+                    Error::config(format!(
+                        "{{dir}} can not be used in the first part of the template {:?}",
+                        template
+                    ))
+                })?),
+                _ if PLACEHOLDERS.contains(&field) => names.get(field),
+                _ => {
+                    return Err(Error::config(format!(
+                        "unknown placeholder {{{}}} in file name template {:?}, expected one of {}",
+                        field,
+                        template,
+                        PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+                    )))
+                }
+            };
+            match value {
+                Some(value) => literal.push_str(value),
+                None => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    tokens.push(Token::Field(field.to_string()));
+                }
+            }
+            rest = &rest[close + 1..];
+        } else {
+            if c == '*' {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(Token::Star);
+            } else {
+                literal.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+// matches the whole of text, what unset placeholders matched is pushed on
+// fields and popped again when the match fails
+fn glob(tokens: &[Token], text: &str, fields: &mut Vec<(String, String)>) -> bool {
+    let Some(token) = tokens.first() else {
+        return text.is_empty();
+    };
+    let rest = &tokens[1..];
+    match token {
+        Token::Literal(literal) => {
+            text.starts_with(literal.as_str()) && glob(rest, &text[literal.len()..], fields)
+        }
+        Token::Star => (0..=text.len())
+            .filter(|&i| text.is_char_boundary(i))
+            .any(|i| glob(rest, &text[i..], fields)),
+        Token::Field(field) => {
+            // already matched in an earlier part of the path
+            if let Some((_, value)) = fields.iter().find(|(name, _)| name == field) {
+                let value = value.clone();
+                return text.starts_with(value.as_str())
+                    && glob(rest, &text[value.len()..], fields);
+            }
+            let word_end = text.find('.').unwrap_or(text.len());
+            for end in (1..=word_end).filter(|&i| text.is_char_boundary(i)) {
+                fields.push((field.clone(), text[..end].to_string()));
+                if glob(rest, &text[end..], fields) {
+                    return true;
+                }
+                fields.pop();
+            }
+            false
+        }
+    }
+}
+
+/// Every file in `search_dir` matching `template`, sorted by path.
+pub fn find_matches(
+    search_dir: &Path,
+    template: &str,
+    names: &Names,
+) -> Result<Vec<TemplateMatch>> {
+    let mut components: Vec<&str> = template.split('/').filter(|c| !c.is_empty()).collect();
+    if components.len() == 1 {
+        components.insert(0, "{genotype}.*");
+    }
+    let mut found = vec![TemplateMatch {
+        path: search_dir.to_path_buf(),
+        fields: Vec::new(),
+    }];
+    for (n, component) in components.iter().enumerate() {
+        let last = n + 1 == components.len();
+        let mut next: Vec<TemplateMatch> = Vec::new();
+        for parent in found {
+            let dir = match n {
+                0 => None,
+                _ => parent.path.file_name().map(|name| name.to_string_lossy()),
+            };
+            let tokens = tokenize(component, template, names, dir.as_deref())?;
+            let files = read_dir(&parent.path).map_err(|err| Error::io(&parent.path, err))?;
+            let mut entries: Vec<PathBuf> = Vec::new();
+            for file in files {
+                entries.push(file.map_err(|err| Error::io(&parent.path, err))?.path());
+            }
+            entries.sort();
+            for path in entries {
+                if (last && !path.is_file()) || (!last && !path.is_dir()) {
+                    continue;
+                }
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let mut fields = parent.fields.clone();
+                if glob(&tokens, &name, &mut fields) {
+                    next.push(TemplateMatch { path, fields });
+                }
+            }
+        }
+        found = next;
+    }
+    Ok(found)
+}
+
+/// The one file in `search_dir` matching `template`, with what its
+/// placeholders matched. Several versions of a genotype are told apart by
+/// setting `assembly` or `annotation` in `names`.
+pub fn find_one(search_dir: &Path, template: &str, names: &Names) -> Result<TemplateMatch> {
+    let mut matches = find_matches(search_dir, template, names)?;
+    match matches.len() {
+        0 => Err(Error::config(format!(
+            "no file in {} matches {:?} with {}",
+            search_dir.display(),
+            template,
+            names.describe()
+        ))),
+        1 => Ok(matches.remove(0)),
+        n => Err(Error::config(format!(
+            "{} files in {} match {:?} with {}, choose the assembly or annotation \
+             version, or make the template more specific:\n  {}",
+            n,
+            search_dir.display(),
+            template,
+            names.describe(),
+            matches
+                .iter()
+                .map(|found| {
+                    let fields: Vec<String> = found
+                        .fields
+                        .iter()
+                        .map(|(field, value)| format!("{}={}", field, value))
+                        .collect();
+                    format!("{} ({})", found.path.display(), fields.join(" "))
+                })
+                .collect::<Vec<String>>()
+                .join("\n  ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ANNOTATION_TEMPLATE, GENOME_TEMPLATE};
+    use std::fs;
+
+    // a fresh directory holding `files`, paths relative to it
+    fn data_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kbrecondo_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn names(genotype: &str) -> Names {
+        Names {
+            species: Some(String::from("medtr")),
+            genotype: Some(String::from(genotype)),
+            ..Names::default()
+        }
+    }
+
+    #[test]
+    fn one_match_reads_the_placeholders() {
+        let dir = data_dir(
+            "paths_one",
+            &[
+                "A17.gnm5.FL4S/medtr.A17.gnm5.FL4S.genome_main.fna.gz",
+                "A17.gnm5.FL4S/README.md",
+                "R108.gnm1.ZZ11/medtr.R108.gnm1.ZZ11.genome_main.fna.gz",
+                // the assembly of the file has to be the one of its directory
+                "A17.gnm4.AB12/medtr.A17.gnm5.AB12.genome_main.fna.gz",
+            ],
+        );
+        let found = find_one(&dir, GENOME_TEMPLATE, &names("A17")).unwrap();
+        assert_eq!(
+            found.path,
+            dir.join("A17.gnm5.FL4S/medtr.A17.gnm5.FL4S.genome_main.fna.gz")
+        );
+        assert_eq!(found.field("assembly"), Some("gnm5"));
+        assert_eq!(found.field("genotype"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn several_matches_are_an_error() {
+        let dir = data_dir(
+            "paths_several",
+            &[
+                "A17.gnm4.AB12/medtr.A17.gnm4.AB12.genome_main.fna.gz",
+                "A17.gnm5.FL4S/medtr.A17.gnm5.FL4S.genome_main.fna.gz",
+            ],
+        );
+        assert_eq!(
+            find_matches(&dir, GENOME_TEMPLATE, &names("A17"))
+                .unwrap()
+                .len(),
+            2
+        );
+        let err = find_one(&dir, GENOME_TEMPLATE, &names("A17")).unwrap_err();
+        assert!(err.to_string().contains("2 files"));
+        assert!(err.to_string().contains("assembly=gnm4"));
+        let mut names = names("A17");
+        names.assembly = Some(String::from("gnm4"));
+        let found = find_one(&dir, GENOME_TEMPLATE, &names).unwrap();
+        assert!(found
+            .path
+            .ends_with("medtr.A17.gnm4.AB12.genome_main.fna.gz"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dir_is_the_parent_directory_name() {
+        let dir = data_dir(
+            "paths_dir",
+            &[
+                "A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.cds.fna.gz",
+                "A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.OTHER.cds.fna.gz",
+            ],
+        );
+        let mut names = names("A17");
+        names.seq_type = Some(String::from("cds"));
+        let found = find_one(
+            &dir,
+            "{genotype}.*/{species}.{dir}.{seq_type}.fna.gz",
+            &names,
+        )
+        .unwrap();
+        assert!(found
+            .path
+            .ends_with("medtr.A17.gnm5.ann1_6.L2RX.cds.fna.gz"));
+        assert!(find_matches(&dir, "{dir}.*/x", &names).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn annotation_template_finds_nucleotides_and_proteins() {
+        let dir = data_dir(
+            "paths_protein",
+            &[
+                "A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.cds.fna.gz",
+                "A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.protein.faa.gz",
+                "A17.gnm5.ann1_6.L2RX/medtr.A17.gnm5.ann1_6.L2RX.gff3.gz",
+            ],
+        );
+        let found = find_matches(&dir, ANNOTATION_TEMPLATE, &names("A17")).unwrap();
+        let seq_types: Vec<&str> = found
+            .iter()
+            .filter_map(|found| found.field("seq_type"))
+            .collect();
+        assert_eq!(seq_types, vec!["cds", "protein"]);
+        let mut names = names("A17");
+        names.seq_type = Some(String::from("cds"));
+        let found = find_one(&dir, ANNOTATION_TEMPLATE, &names).unwrap();
+        assert!(found
+            .path
+            .ends_with("medtr.A17.gnm5.ann1_6.L2RX.cds.fna.gz"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn template_without_a_slash_looks_in_the_genotype_directories() {
+        let dir = data_dir(
+            "paths_flat",
+            &[
+                "A17.gnm5.FL4S/medtr.A17.genome.fna",
+                "R108.gnm1.ZZ11/medtr.R108.genome.fna",
+            ],
+        );
+        let found = find_one(&dir, "{species}.{genotype}.genome.fna", &names("R108")).unwrap();
+        assert_eq!(found.path, dir.join("R108.gnm1.ZZ11/medtr.R108.genome.fna"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
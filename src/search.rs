@@ -0,0 +1,476 @@
+//! Finding the pattern in the window around each annotated gene.
+use crate::error::{Error, Result};
+use crate::extract::reverse_complement;
+use crate::fasta::Genome;
+use crate::fmindex::GenomeIndex;
+use crate::header::{chromosome_name, AnnotationRecord, Strand};
+use crate::matcher;
+use crate::window::{build_window, Window};
+
+/// One occurrence of the pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    /// Offset of the first base of the occurrence in the chromosome, the
+    /// occurrence is `location..location + pattern length`.
+    pub location: usize,
+    /// Bases differing from the pattern.
+    pub mismatches: usize,
+}
+
+/// The hits found in the window of one annotation record.
+#[derive(Clone, Debug)]
+pub struct GeneHits {
+    pub record: AnnotationRecord,
+    pub window: Window,
+    pub hits: Vec<Hit>,
+}
+
+/// Per gene numbers of a search, one for every searched gene, with or
+/// without hits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneSummary {
+    pub hits: usize,
+    /// Bases searched, see [`Window::len`].
+    pub window_len: usize,
+    pub hits_per_kb: f64,
+    /// First hit in the direction of transcription.
+    pub first_hit: Option<usize>,
+    /// Hit nearest the transcription start, the gene's `begin` on `+` and
+    /// `end` on `-`.
+    pub closest_hit: Option<usize>,
+    /// Distance from the transcription start to the 5' end of the closest
+    /// hit, in the direction of transcription, negative upstream.
+    pub closest_distance: Option<i64>,
+}
+
+impl GeneSummary {
+    pub fn new(gene: &GeneHits, pattern_len: usize) -> GeneSummary {
+        let record = &gene.record;
+        let window_len = gene.window.len();
+        let hits_per_kb = if window_len == 0 {
+            0.0
+        } else {
+            gene.hits.len() as f64 * 1000.0 / window_len as f64
+        };
+        // 0 based offsets, as the hit locations
+        let (tss, first_hit) = match record.strand {
+            Strand::Plus => (
+                record.begin as i64 - 1,
+                gene.hits.iter().map(|hit| hit.location).min(),
+            ),
+            Strand::Minus => (
+                record.end as i64 - 1,
+                gene.hits.iter().map(|hit| hit.location).max(),
+            ),
+        };
+        let distance = |location: usize| match record.strand {
+            Strand::Plus => location as i64 - tss,
+            Strand::Minus => tss - (location + pattern_len) as i64 + 1,
+        };
+        let closest_hit = gene
+            .hits
+            .iter()
+            .map(|hit| hit.location)
+            .min_by_key(|&location| distance(location).abs());
+        GeneSummary {
+            hits: gene.hits.len(),
+            window_len,
+            hits_per_kb,
+            first_hit,
+            closest_hit,
+            closest_distance: closest_hit.map(distance),
+        }
+    }
+}
+
+// legumeinfo names start with species.genotype.gnmN
+fn legumeinfo_assembly(name: &str) -> Option<&str> {
+    name.trim_start_matches('>')
+        .split('.')
+        .nth(2)
+        .filter(|part| part.starts_with("gnm"))
+}
+
+/// Checks that the annotation belongs to the genome with the sequence
+/// `headers`: legumeinfo names have to be of the same assembly, and the
+/// records have to be on chromosomes of the genome. Records on chromosomes
+/// missing from the genome, e.g. unplaced scaffolds, are returned for the
+/// caller to report, unless that is all of them.
+pub fn check_annotation<'a>(
+    headers: &[&str],
+    annotation: &'a [AnnotationRecord],
+) -> Result<Vec<&'a AnnotationRecord>> {
+    let genome_assembly = headers.first().and_then(|h| legumeinfo_assembly(h));
+    let annotation_assembly = annotation
+        .first()
+        .and_then(|record| legumeinfo_assembly(&record.id));
+    if let (Some(genome), Some(ann)) = (genome_assembly, annotation_assembly) {
+        if genome != ann {
+            return Err(Error::validation(
+                &annotation[0].id,
+                format!(
+                    "the annotation is of assembly {} but the genome is {}",
+                    ann, genome
+                ),
+            ));
+        }
+    }
+
+    let names: Vec<String> = headers.iter().map(|h| chromosome_name(h)).collect();
+    let missing: Vec<&AnnotationRecord> = annotation
+        .iter()
+        .filter(|record| !names.contains(&record.chromosome))
+        .collect();
+    if !annotation.is_empty() && missing.len() == annotation.len() {
+        return Err(Error::validation(
+            &annotation[0].id,
+            format!(
+                "none of the {} annotation records are on a sequence of the genome, \
+                 e.g. chromosome {} is not one of {}",
+                annotation.len(),
+                annotation[0].chromosome,
+                names.join(", ")
+            ),
+        ));
+    }
+    Ok(missing)
+}
+
+/// Scans `window` of `seq` for `pattern` with up to `mismatches`
+/// substitutions, occurrences lie completely inside the window. On the `-`
+/// strand the reverse complement is searched for.
+pub fn search_seq(
+    seq: &str,
+    window: &Window,
+    pattern: &str,
+    strand: Strand,
+    mismatches: usize,
+) -> Vec<Hit> {
+    let mut occurances: Vec<Hit> = Vec::new();
+    if window.len() >= pattern.len() && window.right <= seq.len() {
+        let mut pattern = pattern.to_lowercase();
+        if strand == Strand::Minus {
+            pattern = reverse_complement(&pattern);
+        }
+        let search_area = seq.as_bytes()[window.left..window.right].to_ascii_lowercase();
+
+        let pattern = pattern.as_bytes();
+        for i in matcher::find_all(&search_area, pattern, mismatches) {
+            let differ = search_area[i..i + pattern.len()]
+                .iter()
+                .zip(pattern)
+                .filter(|(a, b)| a != b)
+                .count();
+            occurances.push(Hit {
+                location: window.left + i,
+                mismatches: differ,
+            });
+        }
+    }
+
+    occurances
+}
+
+/// Scans the window of every annotation record, see
+/// [`crate::genelist::GeneMatcher`] to search only some genes.
+pub fn search_genome(
+    genome: &Genome,
+    annotation: &[AnnotationRecord],
+    size: usize,
+    pattern: &str,
+    mismatches: usize,
+) -> Vec<GeneHits> {
+    let mut results: Vec<GeneHits> = Vec::new();
+    for chrom in &genome.chromosomes {
+        for record in annotation {
+            if record.chromosome != chrom.name {
+                continue;
+            }
+            let window = build_window(record.begin, record.end, size, chrom.seq.len());
+            let hits = search_seq(&chrom.seq, &window, pattern, record.strand, mismatches);
+            results.push(GeneHits {
+                record: record.clone(),
+                window,
+                hits,
+            });
+        }
+    }
+    results
+}
+
+/// Same as [`search_genome`], but every occurrence on a chromosome is looked
+/// up once in the index and shared by all of its genes. Only exact matches
+/// are supported.
+pub fn index_search(
+    index: &GenomeIndex,
+    annotation: &[AnnotationRecord],
+    size: usize,
+    pattern: &str,
+) -> Vec<GeneHits> {
+    let pattern = pattern.to_lowercase();
+    let inversion = reverse_complement(&pattern);
+    let mut results: Vec<GeneHits> = Vec::new();
+    for chrom_index in &index.chroms {
+        let chromosome = chrom_index.name();
+
+        let plus_hits = chrom_index.locate(pattern.as_bytes());
+        let minus_hits = chrom_index.locate(inversion.as_bytes());
+
+        for record in annotation {
+            if record.chromosome != chromosome {
+                continue;
+            }
+            let hits = if record.strand == Strand::Minus {
+                &minus_hits
+            } else {
+                &plus_hits
+            };
+            let window = build_window(record.begin, record.end, size, chrom_index.len);
+            results.push(GeneHits {
+                record: record.clone(),
+                window,
+                hits: window_hits(hits, &window, pattern.len()),
+            });
+        }
+    }
+    results
+}
+
+// the occurrences lying completely inside the window, as in search_seq
+fn window_hits(hits: &[usize], window: &Window, pattern_len: usize) -> Vec<Hit> {
+    let first = hits.partition_point(|&h| h < window.left);
+    hits[first..]
+        .iter()
+        .take_while(|&&h| h + pattern_len <= window.right)
+        .map(|&location| Hit {
+            location,
+            mismatches: 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::FastaRecord;
+
+    // 40 bases: ACGTA on the first and last bases, at offset 20 and its
+    // reverse complement TACGT at offset 10
+    const SEQ: &str = "ACGTAgggggTACGTgggggACGTAggggggggggACGTA";
+
+    fn genome() -> Genome {
+        Genome::from_fasta(vec![FastaRecord {
+            line: 1,
+            header: String::from(">chr1"),
+            seq: String::from(SEQ),
+        }])
+        .unwrap()
+    }
+
+    fn gene(begin: usize, end: usize, strand: Strand) -> AnnotationRecord {
+        AnnotationRecord {
+            id: String::from("gene1"),
+            gene_name: None,
+            chromosome: String::from("chr1"),
+            begin,
+            end,
+            strand,
+            length: None,
+            locus: None,
+            definition: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    fn locations(results: &[GeneHits]) -> Vec<usize> {
+        results[0].hits.iter().map(|hit| hit.location).collect()
+    }
+
+    #[test]
+    fn hits_map_back_to_the_matched_bases() {
+        let genome = genome();
+        let annotation = [gene(16, 25, Strand::Plus)];
+        let results = search_genome(&genome, &annotation, 100, "acgta", 0);
+        // the first and the last base of the chromosome are searched too
+        assert_eq!(locations(&results), vec![0, 20, 35]);
+        for hit in &results[0].hits {
+            assert_eq!(&SEQ[hit.location..hit.location + 5], "ACGTA");
+        }
+    }
+
+    #[test]
+    fn minus_strand_hits_are_the_reverse_complement() {
+        let genome = genome();
+        let annotation = [gene(16, 25, Strand::Minus)];
+        let results = search_genome(&genome, &annotation, 100, "acgta", 0);
+        assert_eq!(locations(&results), vec![10]);
+        assert_eq!(&SEQ[10..15], "TACGT");
+    }
+
+    #[test]
+    fn hits_lie_completely_inside_the_window() {
+        let genome = genome();
+        // bases 21..=25 are offsets 20..25, the hit at 20 fills the window
+        let results = search_genome(&genome, &[gene(21, 25, Strand::Plus)], 0, "acgta", 0);
+        assert_eq!(
+            results[0].window,
+            Window {
+                left: 20,
+                right: 25
+            }
+        );
+        assert_eq!(locations(&results), vec![20]);
+        // one base less on either side and it is not found
+        let results = search_genome(&genome, &[gene(22, 25, Strand::Plus)], 0, "acgta", 0);
+        assert!(results[0].hits.is_empty());
+        let results = search_genome(&genome, &[gene(21, 24, Strand::Plus)], 0, "acgta", 0);
+        assert!(results[0].hits.is_empty());
+    }
+
+    #[test]
+    fn index_search_finds_the_same_hits() {
+        let genome = genome();
+        let index = GenomeIndex::build(&genome);
+        for annotation in [
+            [gene(16, 25, Strand::Plus)],
+            [gene(16, 25, Strand::Minus)],
+            [gene(21, 25, Strand::Plus)],
+            [gene(21, 24, Strand::Plus)],
+        ] {
+            for size in [0, 3, 100] {
+                let scanned = search_genome(&genome, &annotation, size, "acgta", 0);
+                let indexed = index_search(&index, &annotation, size, "acgta");
+                assert_eq!(scanned[0].window, indexed[0].window);
+                assert_eq!(locations(&scanned), locations(&indexed));
+            }
+        }
+    }
+
+    #[test]
+    fn mismatches_are_counted() {
+        let window = Window { left: 0, right: 15 };
+        let hits = search_seq(SEQ, &window, "acgga", Strand::Plus, 1);
+        assert_eq!(
+            hits,
+            vec![Hit {
+                location: 0,
+                mismatches: 1
+            }]
+        );
+    }
+
+    fn gene_hits(strand: Strand, locations: &[usize]) -> GeneHits {
+        GeneHits {
+            record: gene(101, 200, strand),
+            window: Window {
+                left: 0,
+                right: 1000,
+            },
+            hits: locations
+                .iter()
+                .map(|&location| Hit {
+                    location,
+                    mismatches: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn summary_of_a_plus_gene() {
+        // the gene starts at offset 100, hits 50 bases upstream and 150
+        // bases downstream of it
+        let summary = GeneSummary::new(&gene_hits(Strand::Plus, &[250, 50]), 5);
+        assert_eq!(
+            summary,
+            GeneSummary {
+                hits: 2,
+                window_len: 1000,
+                hits_per_kb: 2.0,
+                first_hit: Some(50),
+                closest_hit: Some(50),
+                closest_distance: Some(-50),
+            }
+        );
+        let summary = GeneSummary::new(&gene_hits(Strand::Plus, &[50, 130]), 5);
+        assert_eq!(summary.closest_hit, Some(130));
+        assert_eq!(summary.closest_distance, Some(30));
+    }
+
+    #[test]
+    fn summary_of_a_minus_gene() {
+        // the gene starts at offset 199 and runs to lower offsets, the hit at
+        // 250..255 ends 55 bases upstream, the one at 120..125 is 75 bases
+        // into the gene
+        let summary = GeneSummary::new(&gene_hits(Strand::Minus, &[120, 250]), 5);
+        assert_eq!(
+            summary,
+            GeneSummary {
+                hits: 2,
+                window_len: 1000,
+                hits_per_kb: 2.0,
+                first_hit: Some(250),
+                closest_hit: Some(250),
+                closest_distance: Some(-55),
+            }
+        );
+        let summary = GeneSummary::new(&gene_hits(Strand::Minus, &[160, 300]), 5);
+        assert_eq!(summary.closest_hit, Some(160));
+        assert_eq!(summary.closest_distance, Some(35));
+    }
+
+    #[test]
+    fn summary_without_hits() {
+        let summary = GeneSummary::new(&gene_hits(Strand::Minus, &[]), 5);
+        assert_eq!(summary.hits, 0);
+        assert_eq!(summary.hits_per_kb, 0.0);
+        assert_eq!(summary.first_hit, None);
+        assert_eq!(summary.closest_hit, None);
+        assert_eq!(summary.closest_distance, None);
+    }
+
+    fn record(id: &str, chromosome: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            id: id.to_string(),
+            chromosome: chromosome.to_string(),
+            ..gene(101, 200, Strand::Plus)
+        }
+    }
+
+    #[test]
+    fn annotation_of_another_assembly_is_rejected() {
+        let headers = [
+            ">medtr.A17.gnm5.MtrunA17Chr1",
+            ">medtr.A17.gnm5.MtrunA17Chr2",
+        ];
+        let annotation = [record(
+            "medtr.A17.gnm4.ann2.Medtr1g010010.1",
+            "medtr.A17.gnm5.MtrunA17Chr1",
+        )];
+        let err = check_annotation(&headers, &annotation).unwrap_err();
+        assert!(matches!(err, Error::Validation { .. }), "{}", err);
+        assert!(err.to_string().contains("gnm4"), "{}", err);
+    }
+
+    #[test]
+    fn records_on_missing_chromosomes() {
+        let headers = [
+            ">medtr.A17.gnm5.MtrunA17Chr1",
+            ">medtr.A17.gnm5.MtrunA17Chr2",
+        ];
+        let id = "medtr.A17.gnm5.ann1_6.MtrunA17Chr1g0147.1";
+        let on_chr1 = record(id, "medtr.A17.gnm5.MtrunA17Chr1");
+        let unplaced = record(id, "medtr.A17.gnm5.MtrunA17Chr0c01");
+
+        let annotation = [on_chr1.clone(), unplaced.clone()];
+        let missing = check_annotation(&headers, &annotation).unwrap();
+        assert_eq!(missing, [&unplaced]);
+
+        let annotation = [on_chr1];
+        assert!(check_annotation(&headers, &annotation).unwrap().is_empty());
+
+        let annotation = [unplaced.clone(), unplaced];
+        let err = check_annotation(&headers, &annotation).unwrap_err();
+        assert!(matches!(err, Error::Validation { .. }), "{}", err);
+    }
+}
@@ -0,0 +1,128 @@
+//! The region searched around each gene, and how positions are written.
+//!
+//! Inside kbrecondo every position is a 0 based offset into the chromosome
+//! sequence and every region is half open, `left..right` as a Rust range:
+//! [`Window`]s, [`crate::Hit`] locations (a hit covers
+//! `location..location + pattern length`). Annotation headers are 1 based
+//! and inclusive, so a gene with `begin=11 end=20` is `10..20`.
+//!
+//! Outputs write them in the [`Coordinates`] the user chose. BED is always
+//! 0 based half open and GFF3 and FASTA headers are always 1 based
+//! inclusive, as their formats say.
+use crate::error::{Error, Result};
+
+/// Offsets into the chromosome sequence searched for a gene, `left..right`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Window {
+    /// Number of bases searched.
+    pub fn len(&self) -> usize {
+        self.right - self.left
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.right == self.left
+    }
+}
+
+/// Extends the 1 based inclusive `begin..=end` of a gene by `window_size` on
+/// both sides, clipped to the chromosome.
+pub fn build_window(begin: usize, end: usize, window_size: usize, seq_len: usize) -> Window {
+    let left_bound = begin.saturating_sub(1).saturating_sub(window_size);
+    let right_bound = if end + window_size > seq_len {
+        seq_len
+    } else {
+        end + window_size
+    };
+    Window {
+        left: left_bound.min(right_bound),
+        right: right_bound,
+    }
+}
+
+/// How positions are written to csv and JSON outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Coordinates {
+    /// 0 based half open, as BED: the first base is 0 and an end is the
+    /// offset after the last base.
+    ZeroBased,
+    /// 1 based inclusive, as GFF3 and the annotation headers: the first base
+    /// is 1 and an end is the last base.
+    #[default]
+    OneBased,
+}
+
+impl Coordinates {
+    /// `0` (or `0-based`, `bed`) or `1` (or `1-based`, `gff`).
+    pub fn parse(coordinates: &str) -> Result<Coordinates> {
+        match coordinates.to_lowercase().as_str() {
+            "0" | "0-based" | "bed" => Ok(Coordinates::ZeroBased),
+            "1" | "1-based" | "gff" => Ok(Coordinates::OneBased),
+            _ => Err(Error::config(format!(
+                "unknown coordinates {:?}, expected 0 (0 based half open) or 1 (1 based inclusive)",
+                coordinates
+            ))),
+        }
+    }
+
+    /// First base of a region, or a single position, given as a 0 based
+    /// offset.
+    pub fn start(&self, offset: usize) -> usize {
+        match self {
+            Coordinates::ZeroBased => offset,
+            Coordinates::OneBased => offset + 1,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Coordinates::ZeroBased => "0-based",
+            Coordinates::OneBased => "1-based",
+        }
+    }
+
+    /// End of a region given as a 0 based half open end. The offset after
+    /// the last base is the last base counted from 1, so both are the same.
+    pub fn end(&self, end: usize) -> usize {
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_covers_the_gene_and_both_flanks() {
+        // gene at bases 11..=20, offsets 10..20
+        let window = build_window(11, 20, 5, 100);
+        assert_eq!(window, Window { left: 5, right: 25 });
+        assert_eq!(window.len(), 5 + 10 + 5);
+    }
+
+    #[test]
+    fn window_is_clipped_to_the_chromosome() {
+        let window = build_window(3, 20, 5, 22);
+        assert_eq!(window, Window { left: 0, right: 22 });
+        // a gene starting on the first base keeps the first base
+        assert_eq!(build_window(1, 10, 5, 100).left, 0);
+        assert_eq!(build_window(6, 10, 5, 100).left, 0);
+        assert_eq!(build_window(7, 10, 5, 100).left, 1);
+    }
+
+    #[test]
+    fn coordinates_convert_half_open_offsets() {
+        // offsets 10..20 are the bases 11 to 20
+        assert_eq!(Coordinates::ZeroBased.start(10), 10);
+        assert_eq!(Coordinates::ZeroBased.end(20), 20);
+        assert_eq!(Coordinates::OneBased.start(10), 11);
+        assert_eq!(Coordinates::OneBased.end(20), 20);
+        assert_eq!(Coordinates::parse("bed").unwrap(), Coordinates::ZeroBased);
+        assert_eq!(Coordinates::parse("1").unwrap(), Coordinates::OneBased);
+        assert!(Coordinates::parse("2").is_err());
+    }
+}